@@ -0,0 +1,204 @@
+//! Threshold alarms with hysteresis and debounce
+//!
+//! An alarm is raised once a reading stays above the high threshold (or
+//! below the low threshold) for at least the configured duration. It clears
+//! only after the reading goes back past the threshold by the hysteresis
+//! margin, so a value hovering around a threshold does not toggle the alarm.
+
+use embassy_time::{Duration, Instant};
+
+/// Alarm thresholds
+#[derive(Debug, Copy, Clone)]
+pub struct AlarmConfig {
+    /// Readings above this value raise a high alarm
+    pub high: f64,
+    /// Readings below this value raise a low alarm
+    pub low: f64,
+    /// Margin the reading has to go back past the threshold to clear the alarm
+    pub hysteresis: f64,
+    /// How long the threshold has to be exceeded before the alarm is raised
+    pub min_duration: Duration,
+}
+
+/// Which threshold was exceeded
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum AlarmKind {
+    /// Reading above the high threshold
+    High,
+    /// Reading below the low threshold
+    Low,
+}
+
+/// Alarm state change reported by [`Alarm::update`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum AlarmEvent {
+    /// The alarm was raised
+    Raised(AlarmKind),
+    /// The alarm cleared
+    Cleared(AlarmKind),
+}
+
+/// Alarm state machine
+pub struct Alarm {
+    config: AlarmConfig,
+    active: Option<AlarmKind>,
+    acknowledged: bool,
+    pending: Option<(AlarmKind, Instant)>,
+}
+
+impl Alarm {
+    /// Creates an alarm in the normal state
+    pub const fn new(config: AlarmConfig) -> Self {
+        Self {
+            config,
+            active: None,
+            acknowledged: false,
+            pending: None,
+        }
+    }
+
+    /// Feeds a reading taken at `now`, returns the state change, if any
+    pub fn update(&mut self, now: Instant, value: f64) -> Option<AlarmEvent> {
+        if let Some(kind) = self.active {
+            let cleared = match kind {
+                AlarmKind::High => value < self.config.high - self.config.hysteresis,
+                AlarmKind::Low => value > self.config.low + self.config.hysteresis,
+            };
+            if cleared {
+                self.active = None;
+                self.acknowledged = false;
+                return Some(AlarmEvent::Cleared(kind));
+            }
+            return None;
+        }
+
+        let breach = if value > self.config.high {
+            Some(AlarmKind::High)
+        } else if value < self.config.low {
+            Some(AlarmKind::Low)
+        } else {
+            None
+        };
+
+        match (breach, self.pending) {
+            (Some(kind), Some((pending, since))) if kind == pending => {
+                if now.saturating_duration_since(since) >= self.config.min_duration {
+                    self.pending = None;
+                    self.active = Some(kind);
+                    return Some(AlarmEvent::Raised(kind));
+                }
+            }
+            (Some(kind), _) => self.pending = Some((kind, now)),
+            (None, _) => self.pending = None,
+        }
+        None
+    }
+
+    /// Acknowledges the active alarm, it stays active but stops sounding
+    pub fn acknowledge(&mut self) {
+        if self.active.is_some() {
+            self.acknowledged = true;
+        }
+    }
+
+    /// Returns the active alarm
+    pub fn active(&self) -> Option<AlarmKind> {
+        self.active
+    }
+
+    /// Returns the active alarm, unless it was acknowledged
+    pub fn sounding(&self) -> Option<AlarmKind> {
+        self.active.filter(|_| !self.acknowledged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: AlarmConfig = AlarmConfig {
+        high: 28.0,
+        low: 16.0,
+        hysteresis: 1.0,
+        min_duration: Duration::from_secs(10),
+    };
+
+    /// Returns the instant `seconds` seconds after boot
+    fn at(seconds: u64) -> Instant {
+        Instant::from_secs(seconds)
+    }
+
+    #[test]
+    fn raised_after_min_duration() {
+        let mut alarm = Alarm::new(CONFIG);
+        assert_eq!(alarm.update(at(0), 29.0), None);
+        assert_eq!(alarm.update(at(9), 29.0), None);
+        assert_eq!(alarm.active(), None);
+        assert_eq!(
+            alarm.update(at(10), 29.0),
+            Some(AlarmEvent::Raised(AlarmKind::High))
+        );
+        assert_eq!(alarm.sounding(), Some(AlarmKind::High));
+        assert_eq!(alarm.update(at(11), 29.0), None);
+    }
+
+    #[test]
+    fn short_breach_is_ignored() {
+        let mut alarm = Alarm::new(CONFIG);
+        alarm.update(at(0), 15.0);
+        // Back in range, the debounce starts over
+        alarm.update(at(5), 20.0);
+        assert_eq!(alarm.update(at(10), 15.0), None);
+        assert_eq!(alarm.update(at(19), 15.0), None);
+        assert_eq!(
+            alarm.update(at(20), 15.0),
+            Some(AlarmEvent::Raised(AlarmKind::Low))
+        );
+    }
+
+    #[test]
+    fn cleared_below_hysteresis() {
+        let mut alarm = Alarm::new(CONFIG);
+        alarm.update(at(0), 29.0);
+        alarm.update(at(10), 29.0);
+        // Below the threshold but within the hysteresis margin
+        assert_eq!(alarm.update(at(11), 27.5), None);
+        assert_eq!(alarm.update(at(12), 27.0), None);
+        assert_eq!(alarm.active(), Some(AlarmKind::High));
+        assert_eq!(
+            alarm.update(at(13), 26.9),
+            Some(AlarmEvent::Cleared(AlarmKind::High))
+        );
+        assert_eq!(alarm.active(), None);
+
+        alarm.update(at(20), 15.0);
+        alarm.update(at(30), 15.0);
+        assert_eq!(alarm.update(at(31), 17.0), None);
+        assert_eq!(
+            alarm.update(at(32), 17.1),
+            Some(AlarmEvent::Cleared(AlarmKind::Low))
+        );
+    }
+
+    #[test]
+    fn acknowledged_until_cleared() {
+        let mut alarm = Alarm::new(CONFIG);
+        // Nothing to acknowledge yet
+        alarm.acknowledge();
+        alarm.update(at(0), 29.0);
+        alarm.update(at(10), 29.0);
+        assert_eq!(alarm.sounding(), Some(AlarmKind::High));
+
+        alarm.acknowledge();
+        assert_eq!(alarm.sounding(), None);
+        assert_eq!(alarm.active(), Some(AlarmKind::High));
+        assert_eq!(alarm.update(at(100), 35.0), None);
+        assert_eq!(alarm.sounding(), None);
+
+        // Once cleared, the next alarm sounds again
+        alarm.update(at(101), 20.0);
+        alarm.update(at(110), 29.0);
+        alarm.update(at(120), 29.0);
+        assert_eq!(alarm.sounding(), Some(AlarmKind::High));
+    }
+}
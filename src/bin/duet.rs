@@ -0,0 +1,33 @@
+#![no_main]
+#![no_std]
+
+use defmt_rtt as _;
+use embassy_executor::Spawner;
+use embassy_mar_2025::music::library::{ODE_TO_JOY, ODE_TO_JOY_BASS};
+use embassy_mar_2025::music::*;
+use embassy_rp::pwm::Pwm;
+use embassy_time::Timer;
+use panic_probe as _;
+
+static TRACKS: [&[(f64, i16)]; 2] = [&ODE_TO_JOY.notes, &ODE_TO_JOY_BASS.notes];
+
+/// Commands for the sequencer
+static PLAYER: PlayerControl = PlayerControl::new();
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let peripherals = embassy_rp::init(Default::default());
+
+    let melody = Pwm::new_output_b(peripherals.PWM_SLICE1, peripherals.PIN_3, Default::default());
+    let bass = Pwm::new_output_b(peripherals.PWM_SLICE2, peripherals.PIN_5, Default::default());
+    let mut sequencer = Sequencer::new([melody, bass], &PLAYER);
+
+    let score = Score {
+        song: ODE_TO_JOY.song(),
+        tracks: &TRACKS,
+    };
+    loop {
+        let _ = sequencer.play(&score).await;
+        Timer::after_secs(1).await;
+    }
+}
@@ -0,0 +1,103 @@
+#![no_main]
+#![no_std]
+
+use defmt::{error, info};
+use defmt_rtt as _;
+use embassy_executor::Spawner;
+use embassy_mar_2025::music::*;
+use embassy_rp::gpio::{Level, Output};
+use embassy_rp::pwm::Pwm;
+use embassy_rp::spi::{self, Spi};
+use embassy_time::{Delay, Timer};
+use embedded_hal_bus::spi::ExclusiveDevice;
+use embedded_sdmmc::{SdCard, TimeSource, Timestamp, VolumeManager};
+use panic_probe as _;
+
+/// Directory of the card holding the songs
+const SONGS_DIR: &str = "SONGS";
+
+/// Commands for the melody player
+static PLAYER: PlayerControl = PlayerControl::new();
+
+/// The songs are only read, so file times are never written
+struct NoClock;
+
+impl TimeSource for NoClock {
+    fn get_timestamp(&self) -> Timestamp {
+        Timestamp {
+            year_since_1970: 55,
+            zero_indexed_month: 0,
+            zero_indexed_day: 0,
+            hours: 0,
+            minutes: 0,
+            seconds: 0,
+        }
+    }
+}
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let peripherals = embassy_rp::init(Default::default());
+
+    // The buzzer is on GP7
+    let pwm = Pwm::new_output_b(
+        peripherals.PWM_SLICE3,
+        peripherals.PIN_7,
+        Default::default(),
+    );
+    let mut player = Player::new(pwm, &PLAYER);
+    player.set_envelope(Envelope::PIANO);
+
+    // The SD card is on SPI0, SCK on GP18, MOSI on GP19, MISO on GP16 and
+    // CS on GP17. Cards start at 400 kHz at most.
+    let mut config = spi::Config::default();
+    config.frequency = 400_000;
+    let bus = Spi::new_blocking(
+        peripherals.SPI0,
+        peripherals.PIN_18,
+        peripherals.PIN_19,
+        peripherals.PIN_16,
+        config,
+    );
+    let cs = Output::new(peripherals.PIN_17, Level::High);
+    let card = SdCard::new(ExclusiveDevice::new(bus, cs, Delay), Delay);
+    let mut volumes = VolumeManager::new(card, NoClock);
+
+    let mut loader = match SongLoader::open(&mut volumes, Some(SONGS_DIR)) {
+        Ok(loader) => loader,
+        Err(e) => {
+            error!(
+                "Cannot open the {} directory: {}",
+                SONGS_DIR,
+                defmt::Debug2Format(&e)
+            );
+            return;
+        }
+    };
+    let songs = match loader.scan() {
+        Ok(songs) => songs,
+        Err(e) => {
+            error!("Cannot list the songs: {}", defmt::Debug2Format(&e));
+            return;
+        }
+    };
+    info!("{} songs found", songs.len());
+
+    loop {
+        for song in &songs {
+            info!(
+                "Playing {} ({})",
+                defmt::Display2Format(&song.name),
+                song.format
+            );
+            if let Err(e) = loader.play(&mut player, song).await {
+                error!(
+                    "Cannot play {}: {}",
+                    defmt::Display2Format(&song.name),
+                    defmt::Debug2Format(&e)
+                );
+            }
+            Timer::after_secs(1).await;
+        }
+    }
+}
@@ -0,0 +1,97 @@
+#![no_main]
+#![no_std]
+
+use defmt::info;
+use defmt_rtt as _;
+use embassy_executor::Spawner;
+use embassy_futures::select::{Either3, select3};
+use embassy_mar_2025::music::*;
+use embassy_rp::gpio::{Input, Level, Output, Pull};
+use embassy_rp::pwm::Pwm;
+use embassy_time::{Instant, Timer};
+use panic_probe as _;
+
+/// Time signatures cycled by the time signature button
+const TIME_SIGNATURES: [TimeSignature; 4] = [
+    TimeSignature::COMMON,
+    TimeSignature {
+        beats: 3,
+        unit: NoteValue::Quarter,
+    },
+    TimeSignature {
+        beats: 2,
+        unit: NoteValue::Quarter,
+    },
+    TimeSignature {
+        beats: 6,
+        unit: NoteValue::Eighth,
+    },
+];
+
+/// Commands for the metronome
+static METRONOME: MetronomeControl = MetronomeControl::new();
+/// The buzzer is only used by the metronome
+static PLAYER: PlayerControl = PlayerControl::new();
+
+/// Maps the buttons to tap tempo, start/stop and time signature
+#[embassy_executor::task]
+async fn buttons_task(
+    mut tap: Input<'static>,
+    mut start: Input<'static>,
+    mut signature: Input<'static>,
+) {
+    let mut running = false;
+    let mut signature_index = 0;
+    loop {
+        match select3(
+            tap.wait_for_falling_edge(),
+            start.wait_for_falling_edge(),
+            signature.wait_for_falling_edge(),
+        )
+        .await
+        {
+            Either3::First(_) => METRONOME.send(MetronomeCommand::Tap(Instant::now())).await,
+            Either3::Second(_) => {
+                running = !running;
+                let command = if running {
+                    MetronomeCommand::Start
+                } else {
+                    MetronomeCommand::Stop
+                };
+                METRONOME.send(command).await;
+            }
+            Either3::Third(_) => {
+                signature_index = (signature_index + 1) % TIME_SIGNATURES.len();
+                let time_signature = TIME_SIGNATURES[signature_index];
+                info!("Time signature {}", time_signature);
+                METRONOME
+                    .send(MetronomeCommand::TimeSignature(time_signature))
+                    .await;
+            }
+        }
+        // Debounce
+        Timer::after_millis(50).await;
+    }
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) {
+    let peripherals = embassy_rp::init(Default::default());
+
+    // The buzzer is on GP7 and the LED on GP5, GP2 taps the tempo, GP3
+    // starts and stops, GP6 changes the time signature
+    let pwm = Pwm::new_output_b(
+        peripherals.PWM_SLICE3,
+        peripherals.PIN_7,
+        Default::default(),
+    );
+    let player = Player::new(pwm, &PLAYER);
+    let led = Output::new(peripherals.PIN_5, Level::Low);
+    let metronome = Metronome::new(player, led, &METRONOME, Song::new(100));
+    spawner.spawn(metronome_task(metronome)).unwrap();
+
+    let tap = Input::new(peripherals.PIN_2, Pull::Up);
+    let start = Input::new(peripherals.PIN_3, Pull::Up);
+    let signature = Input::new(peripherals.PIN_6, Pull::Up);
+    spawner.spawn(buttons_task(tap, start, signature)).unwrap();
+}
@@ -0,0 +1,38 @@
+//! Renders a ring tone to a WAV file on the host
+//!
+//! `render <rtttl> <file.wav>`
+
+use std::env;
+use std::process::ExitCode;
+
+use embassy_mar_2025::music::wav::{self, render_file};
+use embassy_mar_2025::music::{Rtttl, Tuning};
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().collect();
+    let [_, rtttl, path] = args.as_slice() else {
+        eprintln!("usage: render <rtttl> <file.wav>");
+        return ExitCode::FAILURE;
+    };
+
+    let rtttl = match Rtttl::parse(rtttl) {
+        Ok(rtttl) => rtttl,
+        Err(err) => {
+            eprintln!("invalid ring tone: {:?}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let melody: Vec<(f64, i16)> = rtttl.melody(Tuning::STANDARD).collect();
+    let song = rtttl.song();
+
+    if let Err(err) = render_file(path, &melody, &song) {
+        eprintln!("cannot write {}: {}", path, err);
+        return ExitCode::FAILURE;
+    }
+    println!(
+        "{} notes, {} ms",
+        melody.len(),
+        wav::duration(&melody, &song).as_millis()
+    );
+    ExitCode::SUCCESS
+}
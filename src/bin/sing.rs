@@ -5,121 +5,189 @@ use core::array;
 use defmt::{error, info};
 use defmt_rtt as _;
 use embassy_executor::Spawner;
-use embassy_futures::select;
+use embassy_futures::select::{Either, Either4, select, select4};
+use embassy_mar_2025::melody;
+use embassy_mar_2025::music::library::{ODE_TO_JOY, Playlist, Repeat, SongEntry, SongLibrary};
+use embassy_mar_2025::music::*;
+use embassy_rp::clocks::RoscRng;
 use embassy_rp::gpio::{Input, Level, Output, Pull};
-use embassy_rp::pwm::{Config as ConfigPmw, Pwm};
+use embassy_rp::pwm::Pwm;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
 use embassy_time::Timer;
-use fixed::traits::ToFixed;
-
-mod music;
-use music::*;
-const TEMPO: u64 = 100;
-/// A whole note duration in milliseconds.
-const WHOLE_NOTE: u64 = 4 * (60_000 / TEMPO);
-/// The microcontroller clock frequency
-const CLOCK_FREQ: u64 = 150_000_000;
-const PWM_DIV: u64 = 64;
+
 /// Game of Thrones Theme
-pub const MELODY: [(f64, i16); 62] = [
-    (REST, 2),
-    (NOTE_D4, 4),
-    (NOTE_G4, -4),
-    (NOTE_AS4, 8),
-    (NOTE_A4, 4),
-    (NOTE_G4, 2),
-    (NOTE_D5, 4),
-    (NOTE_C5, -2),
-    (NOTE_A4, -2),
-    (NOTE_G4, -4),
-    (NOTE_AS4, 8),
-    (NOTE_A4, 4),
-    (NOTE_F4, 2),
-    (NOTE_GS4, 4),
-    (NOTE_D4, -1),
-    (NOTE_D4, 4),
-    (NOTE_G4, -4),
-    (NOTE_AS4, 8),
-    (NOTE_A4, 4),
-    (NOTE_G4, 2),
-    (NOTE_D5, 4),
-    (NOTE_F5, 2),
-    (NOTE_E5, 4),
-    (NOTE_DS5, 2),
-    (NOTE_B4, 4),
-    (NOTE_DS5, -4),
-    (NOTE_D5, 8),
-    (NOTE_CS5, 4),
-    (NOTE_CS4, 2),
-    (NOTE_B4, 4),
-    (NOTE_G4, -1),
-    (NOTE_AS4, 4),
-    (NOTE_D5, 2),
-    (NOTE_AS4, 4),
-    (NOTE_D5, 2),
-    (NOTE_AS4, 4),
-    (NOTE_DS5, 2),
-    (NOTE_D5, 4),
-    (NOTE_CS5, 2),
-    (NOTE_A4, 4),
-    (NOTE_AS4, -4),
-    (NOTE_D5, 8),
-    (NOTE_CS5, 4),
-    (NOTE_CS4, 2),
-    (NOTE_D4, 4),
-    (NOTE_D5, -1),
-    (REST, 4),
-    (NOTE_AS4, 4),
-    (NOTE_D5, 2),
-    (NOTE_AS4, 4),
-    (NOTE_D5, 2),
-    (NOTE_AS4, 4),
-    (NOTE_F5, 2),
-    (NOTE_E5, 4),
-    (NOTE_DS5, 2),
-    (NOTE_B4, 4),
-    (NOTE_DS5, -4),
-    (NOTE_D5, 8),
-    (NOTE_CS5, 4),
-    (NOTE_CS4, 2),
-    (NOTE_AS4, 4),
-    (NOTE_G4, -1),
+pub const MELODY: Melody<62> = melody!(tempo = 200;
+    R/2 D4/4 G4/4. AS4/8 A4/4 G4/2 D5/4 C5/2.
+    A4/2. G4/4. AS4/8 A4/4 F4/2 GS4/4 D4/1. D4/4
+    G4/4. AS4/8 A4/4 G4/2 D5/4 F5/2 E5/4 DS5/2
+    B4/4 DS5/4. D5/8 CS5/4 CS4/2 B4/4 G4/1. AS4/4
+    D5/2 AS4/4 D5/2 AS4/4 DS5/2 D5/4 CS5/2 A4/4
+    AS4/4. D5/8 CS5/4 CS4/2 D4/4 D5/1. R/4 AS4/4
+    D5/2 AS4/4 D5/2 AS4/4 F5/2 E5/4 DS5/2 B4/4
+    DS5/4. D5/8 CS5/4 CS4/2 AS4/4 G4/1.
+);
+/// Twinkle Twinkle Little Star
+const TWINKLE: Melody<14> = melody!(tempo = 110;
+    C4/4 C4/4 G4/4 G4/4 A4/4 A4/4 G4/2
+    F4/4 F4/4 E4/4 E4/4 D4/4 D4/4 C4/2
+);
+
+static SONGS: [SongEntry; 3] = [
+    SongEntry {
+        title: "Game of Thrones",
+        tempo: MELODY.tempo,
+        notes: &MELODY.notes,
+    },
+    SongEntry {
+        title: "Ode to Joy",
+        tempo: ODE_TO_JOY.tempo,
+        notes: &ODE_TO_JOY.notes,
+    },
+    SongEntry {
+        title: "Twinkle Twinkle Little Star",
+        tempo: TWINKLE.tempo,
+        notes: &TWINKLE.notes,
+    },
 ];
-use panic_probe as _;
+static LIBRARY: SongLibrary = SongLibrary::new(&SONGS);
 
+/// Play modes cycled by the mode button, `(repeat, shuffle)`
+const MODES: [(Repeat, bool); 4] = [
+    (Repeat::All, false),
+    (Repeat::One, false),
+    (Repeat::All, true),
+    (Repeat::Off, false),
+];
 
-fn get_note(note_frec: u64) -> u64 {
-    ((CLOCK_FREQ / note_frec) / PWM_DIV) as u64
+/// Skips requested with the buttons
+#[derive(Clone, Copy)]
+enum Skip {
+    Previous,
+    Next,
 }
 
+use panic_probe as _;
 
-#[embassy_executor::main]
-async fn main(_spawner: Spawner) {
-    let peripherals = embassy_rp::init(Default::default());
+/// Commands for the melody player
+static PLAYER: PlayerControl = PlayerControl::new();
+/// Skips to another song, interrupting the current one
+static SKIP: Signal<CriticalSectionRawMutex, Skip> = Signal::new();
+/// Play mode, applied when the current song ends
+static MODE: Signal<CriticalSectionRawMutex, (Repeat, bool)> = Signal::new();
+/// Notes played, shown on the RGB LED
+static NOTES: NoteChannel = NoteChannel::new();
 
-    let mut config: ConfigPmw = Default::default();
-    config.divider = PWM_DIV.to_fixed();
-    config.top = 0x9088;
+/// PWM top of the RGB LED, as in `thermometer.rs`
+const LED_TOP: u16 = 0x9088;
 
-    let mut pwm = Pwm::new_output_b(peripherals.PWM_SLICE1, peripherals.PIN_3, config.clone());
-    let song = Song::new(200);
+#[embassy_executor::task]
+async fn playlist_task(mut player: Player<'static>) {
+    let mut playlist: Playlist<RoscRng, 3> = Playlist::new(LIBRARY, RoscRng);
+    let (repeat, shuffle) = MODES[0];
+    playlist.set_repeat(repeat);
+    playlist.set_shuffle(shuffle);
+
+    let mut song = playlist.current();
     loop {
-        for note in MELODY {
-            if note.0 == REST {
-                let note_dur = song.calc_note_duration(note.1) as u64;
-                Timer::after_millis((note_dur as f64 * 0.9) as u64).await;
-                config.compare_b = 0;
-                Timer::after_millis((note_dur as f64 * 0.1) as u64).await;
-                continue;
+        let Some(entry) = song else {
+            // The playlist is over, a skip starts it again
+            song = skip(&mut playlist, SKIP.wait().await);
+            continue;
+        };
+        info!("Playing {}", entry.title);
+        song = match select(SKIP.wait(), player.play(entry.notes, &entry.song())).await {
+            Either::First(to) => {
+                player.silence();
+                skip(&mut playlist, to)
+            }
+            Either::Second(_) => {
+                if let Some((repeat, shuffle)) = MODE.try_take() {
+                    playlist.set_repeat(repeat);
+                    playlist.set_shuffle(shuffle);
+                }
+                playlist.song_ended()
             }
+        };
+    }
+}
+
+fn skip(playlist: &mut Playlist<RoscRng, 3>, to: Skip) -> Option<&'static SongEntry> {
+    match to {
+        Skip::Previous => playlist.previous_song(),
+        Skip::Next => playlist.next_song(),
+    }
+}
 
-            config.top = (get_note(note.0 as u64)) as u16;
-            config.compare_b = config.top / 2;
-            info!("{}", note.0);
-            pwm.set_config(&config);
-            let note_dur = song.calc_note_duration(note.1) as u64;
-            Timer::after_millis((note_dur as f64 * 0.9) as u64).await;
-            config.compare_b = 0;
-            Timer::after_millis((note_dur as f64 * 0.1) as u64).await;
+/// Maps the buttons to previous, play/pause, next and play mode
+#[embassy_executor::task]
+async fn buttons_task(
+    mut previous: Input<'static>,
+    mut play: Input<'static>,
+    mut next: Input<'static>,
+    mut mode: Input<'static>,
+) {
+    let mut paused = false;
+    let mut mode_index = 0;
+    loop {
+        match select4(
+            previous.wait_for_falling_edge(),
+            play.wait_for_falling_edge(),
+            next.wait_for_falling_edge(),
+            mode.wait_for_falling_edge(),
+        )
+        .await
+        {
+            Either4::First(_) => {
+                paused = false;
+                SKIP.signal(Skip::Previous);
+            }
+            Either4::Second(_) => {
+                paused = !paused;
+                PLAYER.signal(if paused { Command::Pause } else { Command::Resume });
+            }
+            Either4::Third(_) => {
+                paused = false;
+                SKIP.signal(Skip::Next);
+            }
+            Either4::Fourth(_) => {
+                mode_index = (mode_index + 1) % MODES.len();
+                let (repeat, shuffle) = MODES[mode_index];
+                info!("Repeat {}, shuffle {}", repeat, shuffle);
+                MODE.signal(MODES[mode_index]);
+            }
         }
+        // Debounce
+        Timer::after_millis(50).await;
     }
-}
\ No newline at end of file
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) {
+    let peripherals = embassy_rp::init(Default::default());
+
+    // The buzzer is on GP3, the buttons on GP2, GP8, GP4 and GP6
+    let pwm = Pwm::new_output_b(peripherals.PWM_SLICE1, peripherals.PIN_3, Default::default());
+    let mut player = Player::new(pwm, &PLAYER);
+    player.set_envelope(Envelope::PIANO);
+    player.set_note_channel(&NOTES);
+    spawner.spawn(playlist_task(player)).unwrap();
+
+    // GP3 and GP4 are in use, so the RGB LED is wired to GP11 (red), GP12
+    // (green) and GP13 (blue), on the same PWM outputs as in `thermometer.rs`
+    let red = Pwm::new_output_b(peripherals.PWM_SLICE5, peripherals.PIN_11, Default::default());
+    let greenblue = Pwm::new_output_ab(
+        peripherals.PWM_SLICE6,
+        peripherals.PIN_12,
+        peripherals.PIN_13,
+        Default::default(),
+    );
+    let led = RgbLed::new(red, greenblue, LED_TOP);
+    spawner.spawn(light_task(led, &NOTES)).unwrap();
+
+    let previous = Input::new(peripherals.PIN_2, Pull::Up);
+    let play = Input::new(peripherals.PIN_8, Pull::Up);
+    let next = Input::new(peripherals.PIN_4, Pull::Up);
+    let mode = Input::new(peripherals.PIN_6, Pull::Up);
+    spawner.spawn(buttons_task(previous, play, next, mode)).unwrap();
+}
@@ -0,0 +1,36 @@
+#![no_main]
+#![no_std]
+
+use defmt_rtt as _;
+use embassy_executor::Spawner;
+use embassy_mar_2025::music::library::{ODE_TO_JOY, ODE_TO_JOY_BASS};
+use embassy_mar_2025::music::synth::SAMPLE_RATE;
+use embassy_mar_2025::music::*;
+use embassy_time::Timer;
+use panic_probe as _;
+
+static TRACKS: [&[(f64, i16)]; 2] = [&ODE_TO_JOY.notes, &ODE_TO_JOY_BASS.notes];
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let peripherals = embassy_rp::init(Default::default());
+
+    // Speaker behind an RC low pass filter on GP3, the B output of slice 1
+    let mut dac = PwmDac::new(
+        peripherals.PWM_SLICE1,
+        peripherals.PIN_3,
+        peripherals.DMA_CH0,
+        SAMPLE_RATE,
+    )
+    .unwrap();
+
+    let score = Score {
+        song: ODE_TO_JOY.song(),
+        tracks: &TRACKS,
+    };
+    loop {
+        let mut synth = Synth::new(&score, [Waveform::Sine, Waveform::Triangle], SAMPLE_RATE);
+        dac.play(&mut synth).await;
+        Timer::after_secs(1).await;
+    }
+}
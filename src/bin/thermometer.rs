@@ -2,16 +2,28 @@
 #![no_std]
 
 
-use bmp280_ehal::{self, BMP280, Control, Oversampling, PowerMode};
 use core::array;
+use core::cell::RefCell;
 use defmt::{error, info};
 use defmt_rtt as _;
 use embassy_executor::Spawner;
+use embassy_futures::select::{select, Either};
+use embassy_mar_2025::alarm::{Alarm, AlarmConfig, AlarmKind};
+use embassy_mar_2025::bmp280::i2c::BMP280;
+use embassy_mar_2025::bmp280::{Control, Oversampling, PowerMode};
+use embassy_mar_2025::color::{ColorScale, Rgb, SafeRange};
+use embassy_mar_2025::forecast::{self, Forecaster};
+use embassy_mar_2025::music::{Player, PlayerControl, RgbLed, NOTE_A4, NOTE_A6};
+use embassy_mar_2025::stats::{History, SharedHistory};
 use embassy_rp::bind_interrupts;
+use embassy_rp::gpio::{Input, Pull};
 use embassy_rp::i2c::{self, Config, InterruptHandler};
 use embassy_rp::peripherals::I2C1;
 use embassy_rp::pwm::{Config as ConfigPmw, Pwm};
-use embassy_time::Timer;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer};
 use embedded_hal_async::i2c::I2c;
 use fixed::traits::ToFixed;
 use panic_probe as _;
@@ -19,8 +31,129 @@ use panic_probe as _;
 bind_interrupts!(struct Irqs {
     I2C1_IRQ => InterruptHandler<I2C1>;
 });
+
+/// Altitude of the station in meters, used to reduce pressure to sea level
+const ALTITUDE: f64 = 80.0;
+/// Current month (1 to 12), there is no RTC to read it from
+const MONTH: u8 = 3;
+/// Number of 1s readings between two forecast samples
+const FORECAST_EVERY: u32 = forecast::SAMPLE_PERIOD_MIN * 60;
+
+/// PWM top value of the RGB LED slices
+const LED_TOP: u16 = 0x9088;
+/// Colors of the temperature range, in degrees Celsius
+const COLOR_SCALE: ColorScale = ColorScale::new(18.0, 22.0, 26.0);
+/// Outside of this range the LED blinks
+const SAFE_RANGE: SafeRange = SafeRange { min: 15.0, max: 30.0 };
+/// Half period of the LED blinking, in milliseconds
+const BLINK_MS: u64 = 250;
+
+/// Temperature alarm thresholds, in degrees Celsius
+const ALARM_CONFIG: AlarmConfig = AlarmConfig {
+    high: 28.0,
+    low: 16.0,
+    hysteresis: 0.5,
+    min_duration: Duration::from_secs(10),
+};
+/// Buzzer tone of the high temperature alarm, in Hz
+const HIGH_TONE: f64 = NOTE_A6;
+/// Buzzer tone of the low temperature alarm, in Hz
+const LOW_TONE: f64 = NOTE_A4;
+
+/// Temperature history, queried by the other tasks
+static TEMPERATURES: SharedHistory = Mutex::new(RefCell::new(History::new()));
+
+/// Latest temperature, shown by the LED task
+static LED_TEMP: Signal<CriticalSectionRawMutex, f64> = Signal::new();
+
+/// Temperature alarm, updated by the main loop
+static ALARM: Mutex<CriticalSectionRawMutex, RefCell<Alarm>> =
+    Mutex::new(RefCell::new(Alarm::new(ALARM_CONFIG)));
+
+/// Commands for the buzzer player
+static BUZZER: PlayerControl = PlayerControl::new();
+
+/// Shows the latest temperature on the RGB LED
+#[embassy_executor::task]
+async fn led(mut led: RgbLed<'static>) {
+    let mut temp = LED_TEMP.wait().await;
+    let mut lit = true;
+    // The blink follows its own schedule, new readings do not restart it
+    let mut next_blink = Instant::now() + Duration::from_millis(BLINK_MS);
+    loop {
+        let alarm = ALARM.lock(|alarm| alarm.borrow().active());
+        let color = match alarm {
+            Some(_) if !lit => Rgb::OFF,
+            Some(AlarmKind::High) => COLOR_SCALE.hot_color,
+            Some(AlarmKind::Low) => COLOR_SCALE.cold_color,
+            None if lit || SAFE_RANGE.contains(temp) => COLOR_SCALE.color(temp),
+            None => Rgb::OFF,
+        };
+        led.set(color);
+
+        match select(LED_TEMP.wait(), Timer::at(next_blink)).await {
+            Either::First(new) => temp = new,
+            Either::Second(_) => {
+                lit = !lit;
+                next_blink += Duration::from_millis(BLINK_MS);
+            }
+        }
+    }
+}
+
+/// Beeps while an alarm is active and not acknowledged
+#[embassy_executor::task]
+async fn buzzer(mut player: Player<'static>) {
+    loop {
+        let tone = match ALARM.lock(|alarm| alarm.borrow().sounding()) {
+            Some(AlarmKind::High) => HIGH_TONE,
+            Some(AlarmKind::Low) => LOW_TONE,
+            None => {
+                Timer::after_millis(100).await;
+                continue;
+            }
+        };
+
+        let _ = player.tone(tone);
+        Timer::after_millis(500).await;
+        player.silence();
+        Timer::after_millis(500).await;
+    }
+}
+
+/// Acknowledges the active alarm when one of the buttons is pressed
+#[embassy_executor::task]
+async fn acknowledge(mut button1: Input<'static>, mut button4: Input<'static>) {
+    loop {
+        select(button1.wait_for_falling_edge(), button4.wait_for_falling_edge()).await;
+        ALARM.lock(|alarm| alarm.borrow_mut().acknowledge());
+        info!("Alarm acknowledged");
+        Timer::after_millis(200).await;
+    }
+}
+
+/// Logs the temperature statistics every minute
+#[embassy_executor::task]
+async fn report() {
+    loop {
+        Timer::after_secs(60).await;
+        TEMPERATURES.lock(|history| {
+            let history = history.borrow();
+            if let Some(hour) = history.last_hour() {
+                info!(
+                    "Last hour: min {} max {} mean {} stddev {}",
+                    hour.min, hour.max, hour.mean, hour.stddev
+                );
+            }
+            if let Some(day) = history.last_day() {
+                info!("Last 24h: high {} low {}", day.max, day.min);
+            }
+        });
+    }
+}
+
 #[embassy_executor::main]
-async fn main(_spawner: Spawner) {
+async fn main(spawner: Spawner) {
     let peripherals = embassy_rp::init(Default::default());
 
     let mut configred: ConfigPmw = Default::default();
@@ -32,7 +165,7 @@ async fn main(_spawner: Spawner) {
     configgreenblue.compare_b = 0;
     configgreenblue.compare_a = 0;
 
-    let mut red = Pwm::new_output_b(peripherals.PWM_SLICE1, peripherals.PIN_3, configred.clone());
+    let red = Pwm::new_output_b(peripherals.PWM_SLICE1, peripherals.PIN_3, configred.clone());
     let mut greenblue = Pwm::new_output_ab(
         peripherals.PWM_SLICE2,
         peripherals.PIN_4,
@@ -41,41 +174,52 @@ async fn main(_spawner: Spawner) {
     );
     greenblue.set_config(&configgreenblue);
 
+    // GP3 drives the red LED, so the buzzer moves to GP7 and only the
+    // buttons on GP2 and GP6 from `hi.rs` are free
+    let buzzer_pwm = Pwm::new_output_b(peripherals.PWM_SLICE3, peripherals.PIN_7, Default::default());
+    let buzzer_player = Player::new(buzzer_pwm, &BUZZER);
+    let button1 = Input::new(peripherals.PIN_2, Pull::Up);
+    let button4 = Input::new(peripherals.PIN_6, Pull::Up);
+
     let sda = peripherals.PIN_14;
     let scl = peripherals.PIN_15;
 
-    let i2c = i2c::I2c::new_blocking(peripherals.I2C1, scl, sda, Config::default());
-    let mut bmp = bmp280_ehal::BMP280::new(i2c).unwrap();
-    bmp.reset();
+    let i2c = i2c::I2c::new_async(peripherals.I2C1, scl, sda, Irqs, Config::default());
+    let mut bmp = BMP280::new(i2c).unwrap();
+    bmp.reset().await;
     bmp.set_control(Control {
         osrs_t: Oversampling::x2,
         osrs_p: Oversampling::x2,
         mode: PowerMode::Normal,
-    });
+    })
+    .await;
+
+    spawner.spawn(report()).unwrap();
+    spawner.spawn(led(RgbLed::new(red, greenblue, LED_TOP))).unwrap();
+    spawner.spawn(buzzer(buzzer_player)).unwrap();
+    spawner.spawn(acknowledge(button1, button4)).unwrap();
+
+    let mut forecaster = Forecaster::new(ALTITUDE, true);
+    let mut ticks: u32 = 0;
     loop {
-        info!("{}", bmp.temp());
+        // The pressure compensation uses `t_fine`, read the temperature first
+        let temp = bmp.temp().await;
+        info!("{}", temp);
+        TEMPERATURES.lock(|history| history.borrow_mut().push(Instant::now(), temp));
+        LED_TEMP.signal(temp);
+        if let Some(event) = ALARM.lock(|alarm| alarm.borrow_mut().update(Instant::now(), temp)) {
+            info!("Alarm: {}", event);
+        }
+
+        if ticks % FORECAST_EVERY == 0 {
+            forecaster.push(bmp.pressure().await);
+            match forecaster.forecast(MONTH) {
+                Some(forecast) => info!("Forecast {}: {}", forecast.code, forecast.text),
+                None => info!("Forecast: collecting pressure history"),
+            }
+        }
+        ticks = ticks.wrapping_add(1);
+
         Timer::after_millis(1000).await;
     }
 }
-
-/// Sets an RGB value on the LED
-async fn set_rgb(
-    red: &mut Pwm<'_>,
-    greenblue: &mut Pwm<'_>,
-    red_val: u16,
-    green_val: u16,
-    blue_val: u16,
-) {
-    let mut config_red: ConfigPmw = Default::default();
-    config_red.top = 0x9088;
-    config_red.compare_b = red_val;
-
-    let mut config_greenblue: ConfigPmw = Default::default();
-    config_greenblue.top = 0x9088;
-    config_greenblue.compare_a = green_val;
-    config_greenblue.compare_b = blue_val;
-
-    red.set_config(&config_red);
-    greenblue.set_config(&config_greenblue);
-    Timer::after_millis(10).await; // Optional delay if needed
-}
\ No newline at end of file
@@ -0,0 +1,72 @@
+#![no_main]
+#![no_std]
+
+use defmt::{info, warn};
+use defmt_rtt as _;
+use embassy_executor::Spawner;
+use embassy_mar_2025::music::{Reading, Tuning, Yin};
+use embassy_rp::adc::{self, Adc, InterruptHandler};
+use embassy_rp::bind_interrupts;
+use embassy_rp::gpio::{Level, Output, Pull};
+use panic_probe as _;
+
+bind_interrupts!(struct Irqs {
+    ADC_IRQ_FIFO => InterruptHandler;
+});
+
+/// Sample rate of the microphone
+const SAMPLE_RATE: u32 = 8000;
+/// Clock of the ADC, a sample takes `div + 1` cycles
+const ADC_CLOCK: u32 = 48_000_000;
+/// Samples per reading, two periods of the lowest note and some more
+const BLOCK: usize = 1024;
+/// Mid scale of the 12 bit ADC, where the microphone output is biased
+const MID_SCALE: i16 = 2048;
+/// Deviation in cents still shown as in tune
+const TOLERANCE: f32 = 5.0;
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_rp::init(Default::default());
+
+    // The electret microphone module is on GP26 (ADC0), the red, green and
+    // blue LEDs on GP3, GP4 and GP5 show flat, in tune and sharp
+    let mut adc = Adc::new(p.ADC, Irqs, adc::Config::default());
+    let mut mic = adc::Channel::new_pin(p.PIN_26, Pull::None);
+    let mut dma = p.DMA_CH0;
+    let mut flat = Output::new(p.PIN_3, Level::Low);
+    let mut in_tune = Output::new(p.PIN_4, Level::Low);
+    let mut sharp = Output::new(p.PIN_5, Level::Low);
+
+    let yin = Yin::GUITAR;
+    let div = (ADC_CLOCK / SAMPLE_RATE - 1) as u16;
+    let mut raw = [0u16; BLOCK];
+    let mut samples = [0i16; BLOCK];
+    loop {
+        if let Err(e) = adc.read_many(&mut mic, &mut raw, div, &mut dma).await {
+            warn!("ADC error: {}", e);
+            continue;
+        }
+        for (sample, &value) in samples.iter_mut().zip(&raw) {
+            *sample = value as i16 - MID_SCALE;
+        }
+
+        let reading = yin
+            .detect(&samples, SAMPLE_RATE)
+            .and_then(|frequency| Reading::new(frequency, Tuning::STANDARD));
+        let Some(reading) = reading else {
+            flat.set_low();
+            in_tune.set_low();
+            sharp.set_low();
+            continue;
+        };
+        info!(
+            "{} {=f32} Hz, {=f32} cents",
+            reading.pitch, reading.frequency, reading.cents
+        );
+        let tuned = reading.in_tune(TOLERANCE);
+        in_tune.set_level(Level::from(tuned));
+        flat.set_level(Level::from(!tuned && reading.cents < 0.0));
+        sharp.set_level(Level::from(!tuned && reading.cents > 0.0));
+    }
+}
@@ -0,0 +1,109 @@
+#![no_main]
+#![no_std]
+
+use defmt::{info, warn};
+use defmt_rtt as _;
+use embassy_executor::Spawner;
+use embassy_futures::join::join;
+use embassy_mar_2025::music::*;
+use embassy_rp::bind_interrupts;
+use embassy_rp::peripherals::USB;
+use embassy_rp::pwm::Pwm;
+use embassy_rp::usb::{Driver, InterruptHandler};
+use embassy_usb::class::cdc_acm::{CdcAcmClass, State};
+use embassy_usb::{Builder, Config};
+use heapless::Vec;
+use panic_probe as _;
+
+bind_interrupts!(struct Irqs {
+    USBCTRL_IRQ => InterruptHandler<USB>;
+});
+
+/// Longest JSON song, in bytes
+const MAX_JSON: usize = 4096;
+/// Most notes of a song
+const MAX_NOTES: usize = 256;
+
+/// Commands for the melody player
+static PLAYER: PlayerControl = PlayerControl::new();
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let peripherals = embassy_rp::init(Default::default());
+
+    // The buzzer is on GP7
+    let pwm = Pwm::new_output_b(
+        peripherals.PWM_SLICE3,
+        peripherals.PIN_7,
+        Default::default(),
+    );
+    let mut player = Player::new(pwm, &PLAYER);
+    player.set_envelope(Envelope::PIANO);
+
+    let driver = Driver::new(peripherals.USB, Irqs);
+    let mut config = Config::new(0xc0de, 0xcafe);
+    config.manufacturer = Some("Embassy");
+    config.product = Some("Song receiver");
+    config.serial_number = Some("12345678");
+    config.max_power = 100;
+    config.max_packet_size_0 = 64;
+
+    let mut config_descriptor = [0; 256];
+    let mut bos_descriptor = [0; 256];
+    let mut control_buf = [0; 64];
+    let mut state = State::new();
+    let mut builder = Builder::new(
+        driver,
+        config,
+        &mut config_descriptor,
+        &mut bos_descriptor,
+        &mut [],
+        &mut control_buf,
+    );
+    let mut class = CdcAcmClass::new(&mut builder, &mut state, 64);
+    let mut usb = builder.build();
+
+    // Each song is one line of JSON, played as soon as it is received
+    let songs = async {
+        let mut line: Vec<u8, MAX_JSON> = Vec::new();
+        // Bytes are dropped until the end of a line that is too long
+        let mut overflow = false;
+        let mut packet = [0; 64];
+        loop {
+            class.wait_connection().await;
+            info!("Connected, waiting for songs");
+            while let Ok(len) = class.read_packet(&mut packet).await {
+                for &byte in &packet[..len] {
+                    if byte != b'\n' {
+                        if !overflow && line.push(byte).is_err() {
+                            warn!("Song longer than {} bytes", MAX_JSON);
+                            overflow = true;
+                        }
+                        continue;
+                    }
+                    if !overflow {
+                        play(&mut player, &line).await;
+                    }
+                    overflow = false;
+                    line.clear();
+                }
+            }
+            overflow = false;
+            line.clear();
+        }
+    };
+    join(usb.run(), songs).await;
+}
+
+/// Parses and plays one JSON song
+async fn play(player: &mut Player<'_>, json: &[u8]) {
+    match JsonSong::<MAX_NOTES>::parse(json) {
+        Ok(song) => {
+            info!("Playing {} ({} notes)", song.title, song.notes.len());
+            let _ = player
+                .play_iter(song.melody(Tuning::STANDARD), &song.song())
+                .await;
+        }
+        Err(e) => warn!("Invalid song: {}", defmt::Debug2Format(&e)),
+    }
+}
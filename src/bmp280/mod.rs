@@ -5,6 +5,7 @@
 use core::fmt;
 
 pub mod spi;
+pub mod spi3w;
 pub mod i2c;
 
 #[derive(Debug, Copy, Clone)]
@@ -64,7 +65,8 @@ pub enum Filter {
 /// of the device. Note that writing to this register while device in normal
 /// mode may be ignored. Writes in sleep mode are not ignored.
 ///
-/// spi3w_en is intentionally left out of this implementation.
+/// spi3w_en is left out, the SPI driver sets it from the mode it was
+/// created with.
 #[derive(Debug, Copy, Clone)]
 pub struct Config {
     /// Controls inactive duration in normal mode
@@ -1,9 +1,15 @@
 //! A platform agnostic driver to interface with the BMP280 (pressure sensor)
 //!
 //! This driver is built using [`embedded-hal`] traits.
+//!
+//! The sensor can be wired either with separate SDI/SDO lines (4-wire) or
+//! with a single bidirectional data line (3-wire). For the latter, use
+//! [`BMP280::new_3wire`] together with a half-duplex bus such as
+//! [`super::spi3w::Spi3Wire`].
 
 use embassy_futures::block_on;
 use embassy_rp::gpio::Output;
+use embassy_time::Timer;
 
 use super::{Config, Control, Filter, Oversampling, PowerMode, Register, Standby, Status};
 
@@ -11,6 +17,8 @@ use super::{Config, Control, Filter, Oversampling, PowerMode, Register, Standby,
 pub struct BMP280<Spi: embedded_hal_async::spi::SpiBus> {
     com: Spi,
     cs: Output<'static>,
+    // Interface selection, written to the config register
+    spi3w_en: bool,
     // Temperature compensation
     dig_t1: u16,
     dig_t2: i16,
@@ -31,12 +39,32 @@ pub struct BMP280<Spi: embedded_hal_async::spi::SpiBus> {
 impl<Spi: embedded_hal_async::spi::SpiBus> BMP280<Spi> {
     /// Creates new BMP280 driver
     pub fn new<E>(spi: Spi, cs: Output<'static>) -> Result<BMP280<Spi>, E>
+    where
+        Spi: embedded_hal_async::spi::SpiBus<Error = E>,
+    {
+        Self::new_with_mode(spi, cs, false)
+    }
+
+    /// Creates new BMP280 driver that talks to the sensor over a single
+    /// bidirectional data line.
+    ///
+    /// `spi` must be a half-duplex bus: `transfer_in_place` writes the first
+    /// byte and reads the remaining ones on the same line.
+    pub fn new_3wire<E>(spi: Spi, cs: Output<'static>) -> Result<BMP280<Spi>, E>
+    where
+        Spi: embedded_hal_async::spi::SpiBus<Error = E>,
+    {
+        Self::new_with_mode(spi, cs, true)
+    }
+
+    fn new_with_mode<E>(spi: Spi, cs: Output<'static>, spi3w_en: bool) -> Result<BMP280<Spi>, E>
     where
         Spi: embedded_hal_async::spi::SpiBus<Error = E>,
     {
         let mut chip = BMP280 {
             com: spi,
             cs,
+            spi3w_en,
             dig_t1: 0,
             dig_t2: 0,
             dig_t3: 0,
@@ -52,6 +80,11 @@ impl<Spi: embedded_hal_async::spi::SpiBus> BMP280<Spi> {
             dig_p9: 0,
         };
 
+        // The sensor powers up in 4-wire mode, where writes are still received
+        // on SDI, so the interface has to be switched before the first read.
+        if spi3w_en {
+            block_on(chip.write_byte(Register::config, 0b1));
+        }
         block_on(chip.read_calibration());
 
         Ok(chip)
@@ -155,12 +188,14 @@ impl<Spi: embedded_hal_async::spi::SpiBus> BMP280<Spi> {
         }
     }
 
-    /// Sets configuration
+    /// Sets configuration, keeping the SPI interface mode the driver was
+    /// created with
     pub async fn set_config(&mut self, new: Config) {
         let config: u8 = 0x00;
         let t_sb = (new.t_sb as u8) << 5;
         let filter = (new.filter as u8) << 2;
-        self.write_byte(Register::config, config | t_sb | filter).await;
+        let spi3w_en = self.spi3w_en as u8;
+        self.write_byte(Register::config, config | t_sb | filter | spi3w_en).await;
     }
 
     /// Sets control
@@ -219,13 +254,21 @@ impl<Spi: embedded_hal_async::spi::SpiBus> BMP280<Spi> {
     }
 
     /// Software reset, emulates POR
+    ///
+    /// The reset also clears `spi3w_en`, so a 3-wire driver re-enables it
+    /// once the sensor has started up again.
     pub async fn reset(&mut self) {
         self.write_byte(Register::reset, 0xB6).await; // Magic from documentation
+        if self.spi3w_en {
+            Timer::after_millis(2).await; // Start-up time from documentation
+            self.write_byte(Register::config, 0b1).await;
+        }
     }
 
     async fn write_byte(&mut self, reg: Register, byte: u8) {
         self.cs.set_low();
-        let _ = self.com.write(&[reg as u8, byte]).await;
+        // Bit 7 of the control byte selects read (1) or write (0)
+        let _ = self.com.write(&[reg as u8 & 0x7F, byte]).await;
         self.cs.set_high();
     }
 
@@ -0,0 +1,164 @@
+//! A half-duplex SPI bus for sensors wired in 3-wire mode
+//!
+//! The RP2350 SPI peripheral always drives MOSI and samples MISO, so it
+//! cannot share one pin for both directions. This bus runs the transfer on a
+//! PIO state machine instead: it shifts the control bytes out, turns the data
+//! pin around and shifts the answer in on the same line. Chip select is left
+//! to the driver, just like with [`embassy_rp::spi::Spi`].
+
+use core::convert::Infallible;
+
+use embassy_rp::gpio::{Level, Pull};
+use embassy_rp::pio::program::pio_asm;
+use embassy_rp::pio::{
+    instr, Common, Config, Direction, Instance, Irq, PioPin, ShiftConfig, ShiftDirection,
+    StateMachine,
+};
+use fixed::traits::ToFixed;
+
+/// PIO clock divider, each bit takes two PIO cycles (~4.7 MHz at 150 MHz)
+const CLOCK_DIVIDER: u8 = 16;
+
+/// Offset of the `turnaround` label, the entry point of read-only transfers
+const TURNAROUND: u8 = 3;
+
+/// 3-wire SPI bus (mode 0, MSB first) running on a PIO state machine
+///
+/// Transfers are half-duplex:
+/// * `write` only shifts bytes out
+/// * `read` only shifts bytes in
+/// * `transfer` shifts `write` out and then reads `read`
+/// * `transfer_in_place` shifts the first byte out and reads the rest into
+///   the remaining bytes, which is how the BMP280 driver reads registers
+pub struct Spi3Wire<'d, PIO: Instance, const SM: usize> {
+    sm: StateMachine<'d, PIO, SM>,
+    irq: Irq<'d, PIO, 0>,
+    origin: u8,
+}
+
+impl<'d, PIO: Instance, const SM: usize> Spi3Wire<'d, PIO, SM> {
+    /// Creates a new 3-wire bus on the `clk` and `dio` pins
+    pub fn new<CLK: PioPin, DIO: PioPin>(
+        common: &mut Common<'d, PIO>,
+        mut sm: StateMachine<'d, PIO, SM>,
+        irq: Irq<'d, PIO, 0>,
+        clk: CLK,
+        dio: DIO,
+    ) -> Self {
+        let program = pio_asm!(
+            ".side_set 1"
+            ".wrap_target"
+            // write x + 1 bits, the sensor latches them on the rising edge
+            "write:"
+            "    out pins, 1      side 0"
+            "    jmp x-- write    side 1"
+            // y == 0 marks a write-only transfer
+            "    jmp !y done      side 0"
+            // hand the data line over to the sensor
+            "turnaround:"
+            "    set pindirs, 0   side 0"
+            // read y + 1 bits
+            "read:"
+            "    in pins, 1       side 1"
+            "    jmp y-- read     side 0"
+            // signal the end of the transfer, then stall on the next `out`
+            "done:"
+            "    irq 0            side 0"
+            ".wrap"
+        );
+        let loaded = common.load_program(&program.program);
+
+        let pin_clk = common.make_pio_pin(clk);
+        let mut pin_dio = common.make_pio_pin(dio);
+        pin_dio.set_pull(Pull::Up);
+
+        let mut cfg = Config::default();
+        cfg.use_program(&loaded, &[&pin_clk]);
+        cfg.set_out_pins(&[&pin_dio]);
+        cfg.set_in_pins(&[&pin_dio]);
+        cfg.set_set_pins(&[&pin_dio]);
+        cfg.shift_out = ShiftConfig {
+            auto_fill: true,
+            threshold: 8,
+            direction: ShiftDirection::Left,
+        };
+        cfg.shift_in = ShiftConfig {
+            auto_fill: true,
+            threshold: 8,
+            direction: ShiftDirection::Left,
+        };
+        cfg.clock_divider = CLOCK_DIVIDER.to_fixed();
+
+        sm.set_config(&cfg);
+        sm.set_pin_dirs(Direction::Out, &[&pin_clk, &pin_dio]);
+        sm.set_pins(Level::Low, &[&pin_clk, &pin_dio]);
+
+        Self {
+            sm,
+            irq,
+            origin: loaded.origin,
+        }
+    }
+
+    /// Shifts `write` out and then reads `read` from the same line
+    async fn transaction(&mut self, write: &[u8], read: &mut [u8]) {
+        if write.is_empty() && read.is_empty() {
+            return;
+        }
+
+        self.sm.set_enable(false);
+        unsafe {
+            instr::set_x(&mut self.sm, (write.len() * 8).wrapping_sub(1) as u32);
+            instr::set_y(&mut self.sm, (read.len() * 8).saturating_sub(1) as u32);
+            instr::set_pindir(&mut self.sm, 0b1);
+            let entry = if write.is_empty() {
+                self.origin + TURNAROUND
+            } else {
+                self.origin
+            };
+            instr::exec_jmp(&mut self.sm, entry);
+        }
+        self.sm.set_enable(true);
+
+        for byte in write {
+            // `out` shifts from the MSB of the OSR
+            self.sm.tx().wait_push((*byte as u32) << 24).await;
+        }
+        for byte in read.iter_mut() {
+            *byte = self.sm.rx().wait_pull().await as u8;
+        }
+        self.irq.wait().await;
+    }
+}
+
+impl<PIO: Instance, const SM: usize> embedded_hal_async::spi::ErrorType for Spi3Wire<'_, PIO, SM> {
+    type Error = Infallible;
+}
+
+impl<PIO: Instance, const SM: usize> embedded_hal_async::spi::SpiBus for Spi3Wire<'_, PIO, SM> {
+    async fn read(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        self.transaction(&[], words).await;
+        Ok(())
+    }
+
+    async fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        self.transaction(words, &mut []).await;
+        Ok(())
+    }
+
+    async fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
+        self.transaction(write, read).await;
+        Ok(())
+    }
+
+    async fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        if let Some((cmd, data)) = words.split_first_mut() {
+            self.transaction(&[*cmd], data).await;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
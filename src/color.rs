@@ -0,0 +1,121 @@
+//! Mapping of readings onto an RGB LED color
+//!
+//! A [`ColorScale`] goes from a cold color, through a comfortable color, to
+//! a hot color, interpolating linearly between the thresholds.
+
+/// An 8 bit per channel color
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub struct Rgb {
+    /// Red
+    pub red: u8,
+    /// Green
+    pub green: u8,
+    /// Blue
+    pub blue: u8,
+}
+
+impl Rgb {
+    /// LED off
+    pub const OFF: Rgb = Rgb::new(0, 0, 0);
+    /// Pure red
+    pub const RED: Rgb = Rgb::new(255, 0, 0);
+    /// Pure green
+    pub const GREEN: Rgb = Rgb::new(0, 255, 0);
+    /// Pure blue
+    pub const BLUE: Rgb = Rgb::new(0, 0, 255);
+
+    /// Creates a color
+    pub const fn new(red: u8, green: u8, blue: u8) -> Self {
+        Self { red, green, blue }
+    }
+
+    /// Returns the fully saturated color of `hue` in degrees, 0 is red,
+    /// 120 green and 240 blue
+    pub const fn from_hue(hue: u16) -> Rgb {
+        let hue = hue % 360;
+        let rise = ((hue % 60) as u32 * 255 / 60) as u8;
+        let fall = 255 - rise;
+        match hue / 60 {
+            0 => Rgb::new(255, rise, 0),
+            1 => Rgb::new(fall, 255, 0),
+            2 => Rgb::new(0, 255, rise),
+            3 => Rgb::new(0, fall, 255),
+            4 => Rgb::new(rise, 0, 255),
+            _ => Rgb::new(255, 0, fall),
+        }
+    }
+
+    /// Returns the color between `self` (`t = 0`) and `other` (`t = 1`)
+    pub fn lerp(self, other: Rgb, t: f64) -> Rgb {
+        let t = t.clamp(0.0, 1.0);
+        let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * t + 0.5) as u8;
+        Rgb {
+            red: mix(self.red, other.red),
+            green: mix(self.green, other.green),
+            blue: mix(self.blue, other.blue),
+        }
+    }
+
+    /// Scales a channel value to a PWM compare value for the given `top`
+    pub fn duty(channel: u8, top: u16) -> u16 {
+        (channel as u32 * top as u32 / 255) as u16
+    }
+}
+
+/// Color scale with three thresholds
+#[derive(Debug, Copy, Clone)]
+pub struct ColorScale {
+    /// At or below this value the color is `cold_color`
+    pub cold: f64,
+    /// At this value the color is `comfort_color`
+    pub comfort: f64,
+    /// At or above this value the color is `hot_color`
+    pub hot: f64,
+    /// Color of cold readings
+    pub cold_color: Rgb,
+    /// Color of comfortable readings
+    pub comfort_color: Rgb,
+    /// Color of hot readings
+    pub hot_color: Rgb,
+}
+
+impl ColorScale {
+    /// Creates a blue - green - red scale with the given thresholds
+    pub const fn new(cold: f64, comfort: f64, hot: f64) -> Self {
+        Self {
+            cold,
+            comfort,
+            hot,
+            cold_color: Rgb::BLUE,
+            comfort_color: Rgb::GREEN,
+            hot_color: Rgb::RED,
+        }
+    }
+
+    /// Returns the color of `value`
+    pub fn color(&self, value: f64) -> Rgb {
+        if value <= self.comfort {
+            let t = (value - self.cold) / (self.comfort - self.cold);
+            self.cold_color.lerp(self.comfort_color, t)
+        } else {
+            let t = (value - self.comfort) / (self.hot - self.comfort);
+            self.comfort_color.lerp(self.hot_color, t)
+        }
+    }
+}
+
+/// Range of readings considered safe
+#[derive(Debug, Copy, Clone)]
+pub struct SafeRange {
+    /// Lowest safe reading
+    pub min: f64,
+    /// Highest safe reading
+    pub max: f64,
+}
+
+impl SafeRange {
+    /// Returns whether `value` is within the range
+    pub fn contains(&self, value: f64) -> bool {
+        (self.min..=self.max).contains(&value)
+    }
+}
@@ -0,0 +1,304 @@
+//! Zambretti-style weather forecast from the BMP280 pressure history
+//!
+//! The forecaster keeps the last 3 hours of pressure readings, derives the
+//! pressure tendency and looks up one of the 26 Zambretti forecasts (A to Z)
+//! for the sea level pressure, adjusted for altitude and season.
+
+use heapless::Deque;
+
+/// Time between two samples pushed into the history, in minutes
+pub const SAMPLE_PERIOD_MIN: u32 = 10;
+
+/// Length of the history window, in minutes
+pub const WINDOW_MIN: u32 = 180;
+
+/// Number of samples covering the full window, both ends included
+const HISTORY_LEN: usize = (WINDOW_MIN / SAMPLE_PERIOD_MIN) as usize + 1;
+
+/// Minimum amount of history needed to compute a tendency, in samples (1 hour)
+const MIN_SAMPLES: usize = (60 / SAMPLE_PERIOD_MIN) as usize + 1;
+
+/// Pressure change over 3 hours (hPa) under which the pressure is steady
+const STEADY_THRESHOLD: f64 = 1.6;
+
+/// Highest sea level pressure covered by the Zambretti scale (hPa)
+const BARO_TOP: f64 = 1050.0;
+/// Lowest sea level pressure covered by the Zambretti scale (hPa)
+const BARO_BOTTOM: f64 = 950.0;
+
+/// Forecast texts, indexed by the forecast code (A = 0)
+const FORECASTS: [&str; 26] = [
+    "Settled fine",
+    "Fine weather",
+    "Becoming fine",
+    "Fine, becoming less settled",
+    "Fine, possible showers",
+    "Fairly fine, improving",
+    "Fairly fine, possible showers early",
+    "Fairly fine, showery later",
+    "Showery early, improving",
+    "Changeable, mending",
+    "Fairly fine, showers likely",
+    "Rather unsettled clearing later",
+    "Unsettled, probably improving",
+    "Showery, bright intervals",
+    "Showery, becoming less settled",
+    "Changeable, some rain",
+    "Unsettled, short fine intervals",
+    "Unsettled, rain later",
+    "Unsettled, some rain",
+    "Mostly very unsettled",
+    "Occasional rain, worsening",
+    "Rain at times, very unsettled",
+    "Rain at frequent intervals",
+    "Rain, very unsettled",
+    "Stormy, may improve",
+    "Stormy, much rain",
+];
+
+/// Forecast index for each of the 22 pressure bands, lowest band first
+const RISING: [u8; 22] = [
+    25, 25, 25, 24, 24, 19, 16, 12, 11, 9, 8, 6, 5, 2, 1, 1, 0, 0, 0, 0, 0, 0,
+];
+const STEADY: [u8; 22] = [
+    25, 25, 25, 25, 25, 25, 23, 23, 22, 18, 15, 13, 10, 4, 1, 1, 0, 0, 0, 0, 0, 0,
+];
+const FALLING: [u8; 22] = [
+    25, 25, 25, 25, 25, 25, 25, 25, 23, 23, 21, 20, 17, 14, 7, 3, 1, 1, 1, 0, 0, 0,
+];
+
+/// Pressure tendency over the history window
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum Tendency {
+    /// Rising
+    Rising,
+    /// Steady
+    Steady,
+    /// Falling
+    Falling,
+}
+
+/// A Zambretti forecast
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub struct Forecast {
+    /// Forecast letter, from `A` (settled fine) to `Z` (stormy, much rain)
+    pub code: char,
+    /// Short description of the forecast
+    pub text: &'static str,
+}
+
+/// Pressure readings (hPa) of the last 3 hours, oldest first
+pub struct PressureHistory {
+    samples: Deque<f64, HISTORY_LEN>,
+}
+
+impl PressureHistory {
+    /// Creates an empty history
+    pub const fn new() -> Self {
+        Self {
+            samples: Deque::new(),
+        }
+    }
+
+    /// Adds a reading in hPa, expected every [`SAMPLE_PERIOD_MIN`] minutes.
+    /// The oldest reading is dropped once the window is full.
+    pub fn push(&mut self, pressure: f64) {
+        if self.samples.is_full() {
+            self.samples.pop_front();
+        }
+        let _ = self.samples.push_back(pressure);
+    }
+
+    /// Returns the latest reading
+    pub fn latest(&self) -> Option<f64> {
+        self.samples.back().copied()
+    }
+
+    /// Returns the pressure change over the window in hPa, scaled to 3 hours
+    /// when the history is not full yet. Needs at least one hour of history.
+    pub fn change(&self) -> Option<f64> {
+        if self.samples.len() < MIN_SAMPLES {
+            return None;
+        }
+        let (oldest, newest) = (self.samples.front()?, self.samples.back()?);
+        let elapsed = ((self.samples.len() - 1) as u32 * SAMPLE_PERIOD_MIN) as f64;
+        Some((newest - oldest) * WINDOW_MIN as f64 / elapsed)
+    }
+
+    /// Returns the pressure tendency
+    pub fn tendency(&self) -> Option<Tendency> {
+        let change = self.change()?;
+        Some(if change >= STEADY_THRESHOLD {
+            Tendency::Rising
+        } else if change <= -STEADY_THRESHOLD {
+            Tendency::Falling
+        } else {
+            Tendency::Steady
+        })
+    }
+
+    /// Drops all readings
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+}
+
+impl Default for PressureHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Forecaster for a fixed location
+pub struct Forecaster {
+    history: PressureHistory,
+    altitude: f64,
+    northern: bool,
+}
+
+impl Forecaster {
+    /// Creates a forecaster for a station at `altitude` meters, on the
+    /// northern or southern hemisphere
+    pub const fn new(altitude: f64, northern: bool) -> Self {
+        Self {
+            history: PressureHistory::new(),
+            altitude,
+            northern,
+        }
+    }
+
+    /// Adds a station pressure reading in Pa, as returned by the BMP280 driver
+    pub fn push(&mut self, pressure: f64) {
+        self.history.push(sea_level_pressure(pressure / 100.0, self.altitude));
+    }
+
+    /// Returns the pressure history, reduced to sea level
+    pub fn history(&self) -> &PressureHistory {
+        &self.history
+    }
+
+    /// Returns the forecast for the current `month` (1 to 12), or `None`
+    /// while there is not enough history
+    pub fn forecast(&self, month: u8) -> Option<Forecast> {
+        let pressure = self.history.latest()?;
+        let tendency = self.history.tendency()?;
+        Some(zambretti(pressure, tendency, month, self.northern))
+    }
+}
+
+/// Reduces the station pressure (hPa) at `altitude` meters to sea level
+pub fn sea_level_pressure(pressure: f64, altitude: f64) -> f64 {
+    // Barometric formula with a constant scale height, exp(x) is expanded
+    // since `core` has no transcendental functions
+    let x = altitude / 8434.0;
+    pressure * (1.0 + x + x * x / 2.0 + x * x * x / 6.0)
+}
+
+/// Returns the Zambretti forecast for a sea level pressure (hPa)
+pub fn zambretti(pressure: f64, tendency: Tendency, month: u8, northern: bool) -> Forecast {
+    let summer = (4..=9).contains(&month) == northern;
+    let range = BARO_TOP - BARO_BOTTOM;
+
+    let mut pressure = pressure;
+    match tendency {
+        Tendency::Rising if summer => pressure += 0.07 * range,
+        Tendency::Falling if !summer => pressure -= 0.07 * range,
+        _ => {}
+    }
+
+    let band = ((pressure - BARO_BOTTOM) / (range / 22.0)).clamp(0.0, 21.0) as usize;
+    let index = match tendency {
+        Tendency::Rising => RISING[band],
+        Tendency::Steady => STEADY[band],
+        Tendency::Falling => FALLING[band],
+    };
+
+    Forecast {
+        code: (b'A' + index) as char,
+        text: FORECASTS[index as usize],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sea level pressures in hPa every 10 minutes for 3 hours, while a
+    /// high pressure area builds up
+    const RISING_SERIES: [f64; 19] = [
+        1000.1, 1000.4, 1000.6, 1001.0, 1001.3, 1001.5, 1001.9, 1002.2, 1002.4, 1002.8, 1003.1,
+        1003.3, 1003.7, 1004.0, 1004.2, 1004.6, 1004.9, 1005.3, 1005.6,
+    ];
+
+    /// Pressures while a front comes in
+    const FALLING_SERIES: [f64; 19] = [
+        1019.8, 1019.5, 1019.3, 1018.9, 1018.6, 1018.2, 1017.9, 1017.5, 1017.2, 1016.8, 1016.4,
+        1016.1, 1015.7, 1015.3, 1015.0, 1014.6, 1014.2, 1013.9, 1013.5,
+    ];
+
+    /// Pressures under a stable anticyclone
+    const STEADY_SERIES: [f64; 19] = [
+        1029.6, 1029.8, 1029.7, 1029.9, 1030.1, 1030.0, 1029.8, 1029.9, 1030.2, 1030.1, 1029.9,
+        1030.0, 1030.3, 1030.1, 1030.0, 1029.8, 1030.1, 1030.2, 1030.0,
+    ];
+
+    /// Returns a forecaster at `altitude` meters fed with `series` in hPa
+    fn forecaster(altitude: f64, series: &[f64]) -> Forecaster {
+        let mut forecaster = Forecaster::new(altitude, true);
+        for hpa in series {
+            forecaster.push(hpa * 100.0);
+        }
+        forecaster
+    }
+
+    fn assert_forecast(forecast: Option<Forecast>, code: char, text: &str) {
+        let forecast = forecast.expect("enough history");
+        assert_eq!((forecast.code, forecast.text), (code, text));
+    }
+
+    #[test]
+    fn rising_pressure() {
+        let forecaster = forecaster(0.0, &RISING_SERIES);
+        assert_eq!(forecaster.history().tendency(), Some(Tendency::Rising));
+        assert_forecast(forecaster.forecast(1), 'F', "Fairly fine, improving");
+        // A rise in summer is worth more
+        assert_forecast(forecaster.forecast(7), 'C', "Becoming fine");
+    }
+
+    #[test]
+    fn falling_pressure() {
+        let forecaster = forecaster(0.0, &FALLING_SERIES);
+        assert_eq!(forecaster.history().tendency(), Some(Tendency::Falling));
+        assert_forecast(forecaster.forecast(1), 'R', "Unsettled, rain later");
+        assert_forecast(forecaster.forecast(7), 'O', "Showery, becoming less settled");
+    }
+
+    #[test]
+    fn steady_pressure() {
+        let forecaster = forecaster(0.0, &STEADY_SERIES);
+        assert_eq!(forecaster.history().tendency(), Some(Tendency::Steady));
+        assert_forecast(forecaster.forecast(1), 'A', "Settled fine");
+    }
+
+    #[test]
+    fn one_hour_of_history_is_needed() {
+        let forty_minutes = forecaster(0.0, &RISING_SERIES[..6]);
+        assert_eq!(forty_minutes.forecast(1), None);
+        let one_hour = forecaster(0.0, &RISING_SERIES[..7]);
+        assert_eq!(one_hour.history().tendency(), Some(Tendency::Rising));
+    }
+
+    #[test]
+    fn sea_level_reduction() {
+        assert_eq!(sea_level_pressure(1013.25, 0.0), 1013.25);
+        // 950 hPa at 500 m is 950 * exp(500 / 8434) = 1008.02 hPa at sea
+        // level
+        assert!((sea_level_pressure(950.0, 500.0) - 1008.02).abs() < 0.01);
+
+        // A steady 950 hPa at the station is forecast at 1008 hPa
+        let forecaster = forecaster(500.0, &[950.0; 19]);
+        let latest = forecaster.history().latest().unwrap();
+        assert!((latest - 1008.02).abs() < 0.01);
+        assert_forecast(forecaster.forecast(1), 'K', "Fairly fine, showers likely");
+    }
+}
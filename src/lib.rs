@@ -1,4 +1,28 @@
-#![no_std]
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(all(feature = "std", feature = "firmware"))]
+compile_error!("the `std` and `firmware` features cannot be enabled together");
 
 pub mod music;
+pub mod alarm;
+#[cfg(not(feature = "std"))]
 pub mod bmp280;
+pub mod color;
+pub mod forecast;
+pub mod stats;
+
+/// Discards the defmt logs of host builds, which have no RTT
+#[cfg(feature = "std")]
+#[defmt::global_logger]
+struct HostLogger;
+
+#[cfg(feature = "std")]
+unsafe impl defmt::Logger for HostLogger {
+    fn acquire() {}
+
+    unsafe fn flush() {}
+
+    unsafe fn release() {}
+
+    unsafe fn write(_bytes: &[u8]) {}
+}
@@ -0,0 +1,65 @@
+//! Square wave tones on a buzzer driven by a PWM channel
+//!
+//! A [`Buzzer`] owns the `Pwm` of a buzzer on the B output of a slice. The
+//! PWM period of each frequency is solved by [`tone::solve`], and the
+//! loudness is a duty cycle in per mille of 50%, the loudest a square wave
+//! gets. The [`Player`](super::Player) and the
+//! [`Sequencer`](super::Sequencer) both play through it.
+
+use embassy_rp::clocks::clk_sys_freq;
+use embassy_rp::pwm::{Config, Pwm};
+
+use super::envelope;
+use super::tone::{self, ToneError};
+
+/// A buzzer on the B output of a PWM slice
+pub struct Buzzer<'d> {
+    pwm: Pwm<'d>,
+    config: Config,
+    clock: u32,
+}
+
+impl<'d> Buzzer<'d> {
+    /// Creates a silent buzzer on the B output of `pwm`
+    pub fn new(pwm: Pwm<'d>) -> Self {
+        let mut buzzer = Self {
+            pwm,
+            config: Default::default(),
+            clock: clk_sys_freq(),
+        };
+        buzzer.silence();
+        buzzer
+    }
+
+    /// Sets the PWM period for `frequency` Hz, applied by the next
+    /// [`Buzzer::set_level`]
+    pub fn set_frequency(&mut self, frequency: f64) -> Result<(), ToneError> {
+        let tone = tone::solve(self.clock, frequency)?;
+        self.config.divider = tone.divider;
+        self.config.top = tone.top;
+        Ok(())
+    }
+
+    /// Sets the duty cycle to `level` per mille of 50%
+    pub fn set_level(&mut self, level: u16) {
+        let half = self.config.top.div_ceil(2) as u32;
+        self.config.compare_b = (half * level.min(envelope::FULL) as u32 / 1000) as u16;
+        self.pwm.set_config(&self.config);
+    }
+
+    /// Silences the buzzer
+    pub fn silence(&mut self) {
+        self.config.compare_b = 0;
+        self.pwm.set_config(&self.config);
+    }
+
+    /// Silences the buzzer while `mute` is set, without forgetting its
+    /// frequency and level
+    pub fn mute(&mut self, mute: bool) {
+        let mut config = self.config.clone();
+        if mute {
+            config.compare_b = 0;
+        }
+        self.pwm.set_config(&config);
+    }
+}
@@ -0,0 +1,169 @@
+//! Sound effects for user interface feedback
+//!
+//! An [`Effect`] is a short list of [`Segment`]s: fixed tones, frequency
+//! sweeps and silences, optionally repeated. The catalog covers the usual
+//! feedback sounds, and [`effect`] finds them by name.
+
+use embassy_time::Duration;
+
+use super::{NOTE_A5, NOTE_A6, NOTE_C5, NOTE_C6, NOTE_E5, NOTE_G4, NOTE_G5};
+
+/// Part of a sound effect
+#[derive(Debug, Copy, Clone, PartialEq, defmt::Format)]
+pub enum Segment {
+    /// A fixed tone
+    Tone {
+        /// Frequency in Hz
+        frequency: f64,
+        /// Length of the tone
+        duration: Duration,
+    },
+    /// A tone gliding linearly from one frequency to another
+    Sweep {
+        /// Start frequency in Hz
+        from: f64,
+        /// End frequency in Hz
+        to: f64,
+        /// Length of the sweep
+        duration: Duration,
+    },
+    /// Silence
+    Silence(Duration),
+}
+
+impl Segment {
+    /// Returns a tone of `ms` milliseconds
+    pub const fn tone(frequency: f64, ms: u64) -> Segment {
+        Segment::Tone {
+            frequency,
+            duration: Duration::from_millis(ms),
+        }
+    }
+
+    /// Returns a sweep of `ms` milliseconds
+    pub const fn sweep(from: f64, to: f64, ms: u64) -> Segment {
+        Segment::Sweep {
+            from,
+            to,
+            duration: Duration::from_millis(ms),
+        }
+    }
+
+    /// Returns a silence of `ms` milliseconds
+    pub const fn silence(ms: u64) -> Segment {
+        Segment::Silence(Duration::from_millis(ms))
+    }
+
+    /// Returns the length of the segment
+    pub const fn duration(&self) -> Duration {
+        match *self {
+            Segment::Tone { duration, .. } | Segment::Sweep { duration, .. } => duration,
+            Segment::Silence(duration) => duration,
+        }
+    }
+}
+
+/// A named sound effect
+#[derive(Debug, Copy, Clone, PartialEq, defmt::Format)]
+pub struct Effect {
+    /// Name of the effect in the catalog
+    pub name: &'static str,
+    /// Segments played in order
+    pub segments: &'static [Segment],
+    /// Number of times the segments are played
+    pub repeat: u8,
+}
+
+impl Effect {
+    /// Returns the total length of the effect
+    pub fn duration(&self) -> Duration {
+        let once = self
+            .segments
+            .iter()
+            .fold(Duration::from_ticks(0), |total, segment| {
+                total + segment.duration()
+            });
+        once * self.repeat as u32
+    }
+}
+
+/// Very short tick for button presses
+pub const CLICK: Effect = Effect {
+    name: "click",
+    segments: &[Segment::tone(NOTE_A6, 10)],
+    repeat: 1,
+};
+
+/// Rising major third, an action succeeded
+pub const SUCCESS: Effect = Effect {
+    name: "success",
+    segments: &[
+        Segment::tone(NOTE_C5, 80),
+        Segment::silence(20),
+        Segment::tone(NOTE_E5, 150),
+    ],
+    repeat: 1,
+};
+
+/// Two low falling tones, an action failed
+pub const FAILURE: Effect = Effect {
+    name: "failure",
+    segments: &[
+        Segment::tone(NOTE_G4, 150),
+        Segment::silence(30),
+        Segment::sweep(NOTE_G4, NOTE_G4 / 2.0, 300),
+    ],
+    repeat: 1,
+};
+
+/// Three short high beeps, something needs attention
+pub const WARNING: Effect = Effect {
+    name: "warning",
+    segments: &[Segment::tone(NOTE_A6, 80), Segment::silence(80)],
+    repeat: 3,
+};
+
+/// Rising and falling siren, for alarms
+pub const SIREN: Effect = Effect {
+    name: "siren",
+    segments: &[
+        Segment::sweep(NOTE_A5, NOTE_A6, 400),
+        Segment::sweep(NOTE_A6, NOTE_A5, 400),
+    ],
+    repeat: 5,
+};
+
+/// Three beeps, one per second, then a long higher beep
+pub const COUNTDOWN: Effect = Effect {
+    name: "countdown",
+    segments: &[
+        Segment::tone(NOTE_A5, 150),
+        Segment::silence(850),
+        Segment::tone(NOTE_A5, 150),
+        Segment::silence(850),
+        Segment::tone(NOTE_A5, 150),
+        Segment::silence(850),
+        Segment::tone(NOTE_A6, 600),
+    ],
+    repeat: 1,
+};
+
+/// Rising arpeggio played when the board starts
+pub const STARTUP: Effect = Effect {
+    name: "startup",
+    segments: &[
+        Segment::tone(NOTE_C5, 90),
+        Segment::tone(NOTE_E5, 90),
+        Segment::tone(NOTE_G5, 90),
+        Segment::sweep(NOTE_C6, NOTE_C6 * 1.02, 250),
+    ],
+    repeat: 1,
+};
+
+/// All the effects of the catalog
+pub static CATALOG: [Effect; 7] = [CLICK, SUCCESS, FAILURE, WARNING, SIREN, COUNTDOWN, STARTUP];
+
+/// Returns the effect of the catalog called `name`
+pub fn effect(name: &str) -> Option<&'static Effect> {
+    CATALOG.iter().find(|effect| effect.name == name)
+}
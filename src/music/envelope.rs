@@ -0,0 +1,174 @@
+//! Articulation and amplitude envelopes
+//!
+//! A buzzer has no volume control, but the loudness of a square wave grows
+//! with its duty cycle up to 50%. The player follows an ADSR [`Envelope`] by
+//! stepping the duty cycle while a note sounds, and the [`Articulation`] of
+//! each note decides how much of its length is played and how loud.
+
+use embassy_time::Duration;
+
+/// Time between two duty cycle updates while the level changes
+pub const STEP: Duration = Duration::from_millis(5);
+
+/// Full level, in per mille
+pub const FULL: u16 = 1000;
+
+/// How a note is played
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, defmt::Format)]
+pub enum Articulation {
+    /// Sounds for 90% of the note
+    #[default]
+    Normal,
+    /// Sounds for almost the whole note, with a short gap before the next one
+    Legato,
+    /// Sounds for half of the note
+    Staccato,
+    /// Played louder than the other notes
+    Accent,
+    /// Connected to the next note, which continues the envelope without a
+    /// new attack
+    Slur,
+}
+
+impl Articulation {
+    /// Returns the part of the note that sounds, `(numerator, denominator)`
+    pub const fn fraction(self) -> (u32, u32) {
+        match self {
+            Articulation::Normal | Articulation::Accent => (9, 10),
+            Articulation::Legato => (19, 20),
+            Articulation::Staccato => (1, 2),
+            Articulation::Slur => (1, 1),
+        }
+    }
+
+    /// Returns how long a note of `length` sounds, the rest of it is
+    /// silence
+    pub fn sound(self, length: Duration) -> Duration {
+        let (num, den) = self.fraction();
+        length * num / den
+    }
+
+    /// Returns the peak level of the note, in per mille of the volume
+    pub const fn gain(self) -> u16 {
+        match self {
+            Articulation::Accent => 1250,
+            _ => FULL,
+        }
+    }
+}
+
+/// ADSR amplitude envelope
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub struct Envelope {
+    /// Time to rise from silence to full level
+    pub attack: Duration,
+    /// Time to fall from full level to the sustain level
+    pub decay: Duration,
+    /// Level held until the end of the note, in per mille
+    pub sustain: u16,
+    /// Time to fall from the sustain level to silence once the note ends
+    pub release: Duration,
+}
+
+impl Envelope {
+    /// Constant level, each note starts and stops at once
+    pub const ORGAN: Envelope = Envelope {
+        attack: Duration::from_ticks(0),
+        decay: Duration::from_ticks(0),
+        sustain: FULL,
+        release: Duration::from_ticks(0),
+    };
+
+    /// Quick attack and a decay to a soft level, like a piano
+    pub const PIANO: Envelope = Envelope {
+        attack: Duration::from_millis(5),
+        decay: Duration::from_millis(200),
+        sustain: 400,
+        release: Duration::from_millis(50),
+    };
+
+    /// Short plucked notes
+    pub const PLUCK: Envelope = Envelope {
+        attack: Duration::from_ticks(0),
+        decay: Duration::from_millis(100),
+        sustain: 150,
+        release: Duration::from_millis(20),
+    };
+
+    /// Slow swell, like a bowed string
+    pub const STRINGS: Envelope = Envelope {
+        attack: Duration::from_millis(120),
+        decay: Duration::from_millis(80),
+        sustain: 800,
+        release: Duration::from_millis(150),
+    };
+
+    /// Returns the level `elapsed` after the start of the note, in per mille
+    pub fn level(&self, elapsed: Duration) -> u16 {
+        if elapsed < self.attack {
+            return ramp(0, FULL, elapsed, self.attack);
+        }
+        let elapsed = elapsed - self.attack;
+        if elapsed < self.decay {
+            return ramp(FULL, self.sustain, elapsed, self.decay);
+        }
+        self.sustain
+    }
+
+    /// Returns how long after the start of the note the level stops
+    /// changing
+    pub fn settled(&self) -> Duration {
+        self.attack + self.decay
+    }
+
+    /// Returns the level `elapsed` after the end of a note that was at
+    /// `from`, in per mille
+    pub fn release_level(&self, from: u16, elapsed: Duration) -> u16 {
+        if elapsed < self.release {
+            ramp(from, 0, elapsed, self.release)
+        } else {
+            0
+        }
+    }
+
+    /// Returns the level `elapsed` after the start of a note that sounds
+    /// for `sound`, releasing once the sound ends, in per mille
+    pub fn note_level(&self, elapsed: Duration, sound: Duration) -> u16 {
+        if elapsed < sound {
+            self.level(elapsed)
+        } else {
+            self.release_level(self.level(sound), elapsed - sound)
+        }
+    }
+
+    /// Returns the time after the start of a note of `length` that sounds
+    /// for `sound` at which [`Envelope::note_level`] has to be updated
+    /// next, stepping by [`STEP`] while the level changes
+    pub fn next_change(&self, elapsed: Duration, sound: Duration, length: Duration) -> Duration {
+        let next = if elapsed < sound {
+            if elapsed < self.settled() {
+                (elapsed + STEP).min(sound)
+            } else {
+                sound
+            }
+        } else if elapsed < sound + self.release {
+            (elapsed + STEP).min(sound + self.release)
+        } else {
+            length
+        };
+        next.min(length)
+    }
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Self::ORGAN
+    }
+}
+
+/// Linear interpolation from `from` to `to` over `length`
+fn ramp(from: u16, to: u16, elapsed: Duration, length: Duration) -> u16 {
+    let (elapsed, length) = (elapsed.as_ticks() as i64, length.as_ticks().max(1) as i64);
+    let level = from as i64 + (to as i64 - from as i64) * elapsed / length;
+    level as u16
+}
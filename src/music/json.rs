@@ -0,0 +1,109 @@
+//! Songs in JSON
+//!
+//! Songs can be sent to the board as JSON, over USB serial or the network,
+//! and played without reflashing:
+//!
+//! ```json
+//! {
+//!   "title": "Ode to Joy",
+//!   "tempo": 120,
+//!   "notes": [
+//!     { "name": "E4", "duration": 4 },
+//!     { "name": "D4", "duration": 4, "dotted": true },
+//!     { "name": "R", "duration": 8 }
+//!   ]
+//! }
+//! ```
+//!
+//! `name` is a pitch like `"C#4"` or `"Bb3"`, or `"R"` for a rest, and
+//! `duration` divides a whole note, 4 is a quarter note. `dotted` is
+//! optional. Strings are borrowed from the received bytes and the notes are
+//! kept in a `heapless::Vec`, so parsing does not allocate. Strings with
+//! escape sequences cannot be borrowed and are rejected.
+
+use heapless::Vec;
+use serde::Deserialize;
+
+use super::pitch::{Pitch, Tuning};
+use super::rhythm::NoteValue;
+use super::{REST, Song};
+
+/// A note of a JSON song
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+pub struct JsonNote<'a> {
+    /// Pitch name, `"R"` for a rest
+    pub name: &'a str,
+    /// Divider of a whole note, 4 is a quarter note
+    pub duration: u8,
+    /// One and a half times the duration
+    #[serde(default)]
+    pub dotted: bool,
+}
+
+impl JsonNote<'_> {
+    /// Returns the `(frequency, divider)` pair played by the melody player,
+    /// `None` for an invalid name or duration
+    pub fn to_melody_note(&self, tuning: Tuning) -> Option<(f64, i16)> {
+        let frequency = match self.name {
+            "R" | "r" => REST,
+            name => Pitch::parse(name).ok()?.frequency(tuning),
+        };
+        let divider = NoteValue::from_divider(self.duration as u32)?.divider() as i16;
+        Some((frequency, if self.dotted { -divider } else { divider }))
+    }
+}
+
+/// A song of up to `N` notes received as JSON
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct JsonSong<'a, const N: usize> {
+    /// Title of the song
+    pub title: &'a str,
+    /// Tempo in quarter notes per minute
+    pub tempo: u16,
+    /// Notes of the song
+    #[serde(borrow)]
+    pub notes: Vec<JsonNote<'a>, N>,
+}
+
+/// Error returned when parsing a JSON song
+#[derive(Debug)]
+pub enum JsonSongError {
+    /// The JSON is invalid, does not follow the schema or has more notes
+    /// than the song can hold
+    Json(serde_json_core::de::Error),
+    /// The tempo is 0
+    InvalidTempo,
+    /// The note at this index (0 is the first note) is invalid
+    InvalidNote(usize),
+}
+
+impl<'a, const N: usize> JsonSong<'a, N> {
+    /// Parses and validates a song, `json` has to live as long as the song
+    pub fn parse(json: &'a [u8]) -> Result<Self, JsonSongError> {
+        let (song, _): (Self, usize) =
+            serde_json_core::from_slice(json).map_err(JsonSongError::Json)?;
+        if song.tempo == 0 {
+            return Err(JsonSongError::InvalidTempo);
+        }
+        if let Some(index) = song
+            .notes
+            .iter()
+            .position(|note| note.to_melody_note(Tuning::STANDARD).is_none())
+        {
+            return Err(JsonSongError::InvalidNote(index));
+        }
+        Ok(song)
+    }
+
+    /// Returns the song timing
+    pub fn song(&self) -> Song {
+        Song::new(self.tempo)
+    }
+
+    /// Returns the notes as `(frequency, divider)` pairs
+    pub fn melody(&self, tuning: Tuning) -> impl Iterator<Item = (f64, i16)> + use<'_, 'a, N> {
+        self.notes
+            .iter()
+            .filter_map(move |note| note.to_melody_note(tuning))
+    }
+}
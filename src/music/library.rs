@@ -0,0 +1,204 @@
+//! Song library and playlist
+//!
+//! A [`SongLibrary`] is a list of named songs kept in flash as `static`
+//! data. A [`Playlist`] walks through the library in order or shuffled, and
+//! decides what plays next according to its [`Repeat`] mode. Melodies
+//! shared by several programs, like [`ODE_TO_JOY`], are kept here too.
+
+use heapless::Vec;
+use rand::RngCore;
+use rand::seq::SliceRandom;
+
+use super::Song;
+use super::melody::Melody;
+use crate::melody;
+
+/// Melody of Ode to Joy
+pub const ODE_TO_JOY: Melody<15> = melody!(tempo = 120;
+    E4/4 E4/4 F4/4 G4/4 G4/4 F4/4 E4/4 D4/4
+    C4/4 C4/4 D4/4 E4/4 E4/4. D4/8 D4/2
+);
+
+/// Bass line of [`ODE_TO_JOY`], one note per bar
+pub const ODE_TO_JOY_BASS: Melody<4> = melody!(tempo = 120; C3/1 G2/1 C3/1 G2/1);
+
+/// A song of the library
+#[derive(Debug, Copy, Clone)]
+pub struct SongEntry {
+    /// Title of the song
+    pub title: &'static str,
+    /// Tempo in beats per minute
+    pub tempo: u16,
+    /// `(frequency, divider)` notes, negative dividers are dotted
+    pub notes: &'static [(f64, i16)],
+}
+
+impl SongEntry {
+    /// Returns the song timing of the entry
+    pub const fn song(&self) -> Song {
+        Song::new(self.tempo)
+    }
+}
+
+/// Named songs stored in flash
+#[derive(Debug, Copy, Clone)]
+pub struct SongLibrary {
+    songs: &'static [SongEntry],
+}
+
+impl SongLibrary {
+    /// Creates a library of `songs`
+    pub const fn new(songs: &'static [SongEntry]) -> Self {
+        Self { songs }
+    }
+
+    /// Returns the number of songs
+    pub const fn len(&self) -> usize {
+        self.songs.len()
+    }
+
+    /// Returns whether the library has no songs
+    pub const fn is_empty(&self) -> bool {
+        self.songs.is_empty()
+    }
+
+    /// Returns the song at `index`
+    pub fn get(&self, index: usize) -> Option<&'static SongEntry> {
+        self.songs.get(index)
+    }
+
+    /// Returns the song called `title`
+    pub fn find(&self, title: &str) -> Option<&'static SongEntry> {
+        self.songs.iter().find(|song| song.title == title)
+    }
+
+    /// Returns all songs
+    pub fn songs(&self) -> &'static [SongEntry] {
+        self.songs
+    }
+}
+
+/// What happens at the end of a song
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, defmt::Format)]
+pub enum Repeat {
+    /// The playlist stops after the last song
+    #[default]
+    Off,
+    /// The current song plays again
+    One,
+    /// The playlist starts over after the last song
+    All,
+}
+
+/// Order of play over a [`SongLibrary`] of up to `N` songs
+pub struct Playlist<R: RngCore, const N: usize> {
+    library: SongLibrary,
+    order: Vec<usize, N>,
+    position: usize,
+    repeat: Repeat,
+    shuffle: bool,
+    rng: R,
+}
+
+impl<R: RngCore, const N: usize> Playlist<R, N> {
+    /// Creates a playlist over the first `N` songs of `library`, in order,
+    /// `rng` is used to shuffle
+    pub fn new(library: SongLibrary, rng: R) -> Self {
+        Self {
+            order: (0..library.len().min(N)).collect(),
+            library,
+            position: 0,
+            repeat: Repeat::Off,
+            shuffle: false,
+            rng,
+        }
+    }
+
+    /// Returns the song to play, `None` for an empty library
+    pub fn current(&self) -> Option<&'static SongEntry> {
+        let index = *self.order.get(self.position)?;
+        self.library.get(index)
+    }
+
+    /// Returns the position of the current song in the playlist
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Skips to the next song, wrapping around after the last one
+    pub fn next_song(&mut self) -> Option<&'static SongEntry> {
+        if self.position + 1 >= self.order.len() {
+            self.wrap();
+        } else {
+            self.position += 1;
+        }
+        self.current()
+    }
+
+    /// Goes back to the previous song, wrapping around before the first one
+    pub fn previous_song(&mut self) -> Option<&'static SongEntry> {
+        self.position = match self.position {
+            0 => self.order.len().saturating_sub(1),
+            position => position - 1,
+        };
+        self.current()
+    }
+
+    /// Moves on when the current song has ended, following the repeat mode.
+    /// Returns the song to play next, `None` when the playlist is over.
+    pub fn song_ended(&mut self) -> Option<&'static SongEntry> {
+        match self.repeat {
+            Repeat::One => self.current(),
+            Repeat::All => self.next_song(),
+            Repeat::Off if self.position + 1 >= self.order.len() => None,
+            Repeat::Off => self.next_song(),
+        }
+    }
+
+    /// Returns the repeat mode
+    pub fn repeat(&self) -> Repeat {
+        self.repeat
+    }
+
+    /// Changes the repeat mode
+    pub fn set_repeat(&mut self, repeat: Repeat) {
+        self.repeat = repeat;
+    }
+
+    /// Returns whether the playlist is shuffled
+    pub fn shuffle(&self) -> bool {
+        self.shuffle
+    }
+
+    /// Shuffles the playlist or restores the library order. The current
+    /// song stays current.
+    pub fn set_shuffle(&mut self, shuffle: bool) {
+        let Some(&current) = self.order.get(self.position) else {
+            return;
+        };
+        self.shuffle = shuffle;
+        if shuffle {
+            self.order.shuffle(&mut self.rng);
+        } else {
+            self.order.sort_unstable();
+        }
+        self.position = self
+            .order
+            .iter()
+            .position(|&index| index == current)
+            .unwrap_or(0);
+        if shuffle {
+            // Plays the current song first, then the shuffled rest
+            self.order.swap(0, self.position);
+            self.position = 0;
+        }
+    }
+
+    /// Goes back to the first song, with a new order when shuffled
+    fn wrap(&mut self) {
+        if self.shuffle {
+            self.order.shuffle(&mut self.rng);
+        }
+        self.position = 0;
+    }
+}
@@ -0,0 +1,89 @@
+//! Light show on an RGB LED following the notes of a player
+//!
+//! The [`light_task`] receives the notes of a [`Player`](super::Player)
+//! through a [`NoteChannel`]. Each note lights the LED with the hue of its
+//! pitch class, C is red and the hue turns by 30 degrees every semitone,
+//! and fades it out over the length of the note. Rests turn the LED off.
+
+use embassy_futures::select::{Either, select};
+use embassy_rp::pwm::{Config, Pwm};
+use embassy_time::{Duration, Instant, Timer};
+
+use super::pitch::Pitch;
+use super::player::{NoteChannel, NoteEvent};
+use crate::color::Rgb;
+
+/// Time between two brightness steps of a fade
+const FADE_STEP: Duration = Duration::from_millis(20);
+
+/// RGB LED with the red LED on the B output of a PWM slice, and the green
+/// and blue LEDs on the A and B outputs of another one
+pub struct RgbLed<'d> {
+    red: Pwm<'d>,
+    greenblue: Pwm<'d>,
+    top: u16,
+}
+
+impl<'d> RgbLed<'d> {
+    /// Creates a switched off LED, counting to `top` for full brightness
+    pub fn new(red: Pwm<'d>, greenblue: Pwm<'d>, top: u16) -> Self {
+        let mut led = Self {
+            red,
+            greenblue,
+            top,
+        };
+        led.set(Rgb::OFF);
+        led
+    }
+
+    /// Lights the LED with `color`
+    pub fn set(&mut self, color: Rgb) {
+        let mut red: Config = Default::default();
+        red.top = self.top;
+        red.compare_b = Rgb::duty(color.red, self.top);
+
+        let mut greenblue: Config = Default::default();
+        greenblue.top = self.top;
+        greenblue.compare_a = Rgb::duty(color.green, self.top);
+        greenblue.compare_b = Rgb::duty(color.blue, self.top);
+
+        self.red.set_config(&red);
+        self.greenblue.set_config(&greenblue);
+    }
+}
+
+/// Returns the color of a note, off for a rest
+pub fn note_color(pitch: Option<Pitch>) -> Rgb {
+    match pitch {
+        Some(pitch) => Rgb::from_hue((pitch.midi() % 12) as u16 * 30),
+        None => Rgb::OFF,
+    }
+}
+
+/// Shows the notes received on `notes` on `led`
+#[embassy_executor::task]
+pub async fn light_task(mut led: RgbLed<'static>, notes: &'static NoteChannel) {
+    let mut note = notes.receive().await;
+    loop {
+        note = fade(&mut led, note, notes).await;
+    }
+}
+
+/// Fades `note` out until its end, returns the next note as soon as it is
+/// received
+async fn fade(led: &mut RgbLed<'_>, note: NoteEvent, notes: &NoteChannel) -> NoteEvent {
+    let color = note_color(note.pitch);
+    let end = note.start + note.length;
+    let length = note.length.as_micros().max(1) as f64;
+    loop {
+        let remaining = end.saturating_duration_since(Instant::now());
+        led.set(Rgb::OFF.lerp(color, remaining.as_micros() as f64 / length));
+        if remaining.as_ticks() == 0 {
+            return notes.receive().await;
+        }
+        match select(notes.receive(), Timer::after(FADE_STEP)).await {
+            Either::First(next) => return next,
+            Either::Second(_) => {}
+        }
+    }
+}
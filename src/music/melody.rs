@@ -0,0 +1,116 @@
+//! Compile-time checked melodies
+//!
+//! The [`melody!`](crate::melody) macro turns a compact notation into a
+//! [`Melody`]:
+//!
+//! ```ignore
+//! const INTRO: Melody<4> = melody!(tempo = 100; D4/4 G4/4. AS4/8 R/4);
+//! ```
+//!
+//! Each note is `NAME/DURATION`. The name is a pitch like `D4`, `AS4` or
+//! `Bb3` (`S` stands for sharp, since `#` cannot be used) or `R` for a rest.
+//! The duration is 1, 2, 4, 8, 16, 32 or 64, followed by `.` for a dotted
+//! note. Names and durations are checked while compiling, so a typo is a
+//! build error instead of a wrong note.
+
+use super::pitch::{Pitch, Tuning};
+use super::{REST, Song};
+
+/// A melody with its tempo
+#[derive(Debug, Clone, Copy)]
+pub struct Melody<const N: usize> {
+    /// Tempo in beats per minute
+    pub tempo: u16,
+    /// `(frequency, divider)` notes, negative dividers are dotted
+    pub notes: [(f64, i16); N],
+}
+
+impl<const N: usize> Melody<N> {
+    /// Returns the song timing of the melody
+    pub const fn song(&self) -> Song {
+        Song::new(self.tempo)
+    }
+}
+
+/// Returns the frequency of a note name used in [`melody!`](crate::melody)
+#[doc(hidden)]
+pub const fn note(name: &str) -> f64 {
+    match parse_name(name) {
+        Some(frequency) => frequency,
+        None => panic!("unknown note in melody"),
+    }
+}
+
+/// Returns the divider of a duration used in [`melody!`](crate::melody)
+#[doc(hidden)]
+pub const fn divider(duration: &str) -> i16 {
+    match parse_duration(duration) {
+        Some(divider) => divider,
+        None => panic!("invalid duration in melody"),
+    }
+}
+
+/// Parses a `NAME/DURATION` note of the melody notation at runtime, for
+/// melodies that are not known while compiling
+pub fn parse_note(note: &str) -> Option<(f64, i16)> {
+    let (name, duration) = note.split_once('/')?;
+    Some((parse_name(name)?, parse_duration(duration)?))
+}
+
+/// Returns the frequency of a note name, `R` is a rest
+const fn parse_name(name: &str) -> Option<f64> {
+    let bytes = name.as_bytes();
+    if bytes.len() == 1 && bytes[0] == b'R' {
+        return Some(REST);
+    }
+    match Pitch::parse(name) {
+        Ok(pitch) => Some(pitch.frequency(Tuning::STANDARD)),
+        Err(_) => None,
+    }
+}
+
+/// Returns the divider of a duration, negative if dotted
+const fn parse_duration(duration: &str) -> Option<i16> {
+    let bytes = duration.as_bytes();
+    let mut len = bytes.len();
+    let dotted = len > 0 && bytes[len - 1] == b'.';
+    if dotted {
+        len -= 1;
+    }
+
+    let mut value: i16 = 0;
+    let mut i = 0;
+    while i < len {
+        if !bytes[i].is_ascii_digit() || value > 64 {
+            return None;
+        }
+        value = value * 10 + (bytes[i] - b'0') as i16;
+        i += 1;
+    }
+    if !matches!(value, 1 | 2 | 4 | 8 | 16 | 32 | 64) {
+        return None;
+    }
+
+    Some(if dotted { -value } else { value })
+}
+
+/// Builds a [`Melody`] from a compact notation, checked at compile time
+///
+/// ```ignore
+/// let intro = melody!(tempo = 100; D4/4 G4/4. AS4/8 A4/4 R/2);
+/// ```
+#[macro_export]
+macro_rules! melody {
+    (tempo = $tempo:expr; $($note:ident / $duration:literal)*) => {
+        $crate::music::Melody {
+            tempo: const {
+                assert!($tempo > 0, "tempo must be positive");
+                $tempo
+            },
+            notes: [$((
+                const { $crate::music::melody::note(stringify!($note)) },
+                const { $crate::music::melody::divider(stringify!($duration)) },
+            )),*],
+        }
+    };
+}
@@ -0,0 +1,212 @@
+//! Metronome with tap tempo
+//!
+//! The [`Metronome`] clicks every beat of a [`Song`] on the buzzer of a
+//! [`Player`], with a higher click on the first beat of the bar, and flashes
+//! an LED with each click. Clicks are scheduled at absolute instants, one
+//! beat after the previous one, so timing errors do not add up. Other tasks
+//! control it by sending [`MetronomeCommand`]s, taps on a button set the
+//! tempo with [`TapTempo`].
+
+use embassy_futures::select::{Either, select};
+use embassy_rp::gpio::Output;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_time::{Duration, Instant, Timer};
+
+use super::player::Player;
+use super::rhythm::TimeSignature;
+use super::{NOTE_A5, NOTE_E6, Song};
+
+/// Length of a click and of the LED flash
+const CLICK: Duration = Duration::from_millis(40);
+/// Pitch of the first beat of a bar
+const ACCENT: f64 = NOTE_E6;
+/// Pitch of the other beats
+const BEAT: f64 = NOTE_A5;
+/// Slowest tempo, in quarter notes per minute
+pub const MIN_TEMPO: u16 = 20;
+/// Fastest tempo, in quarter notes per minute
+pub const MAX_TEMPO: u16 = 300;
+
+/// Number of taps averaged by [`TapTempo`]
+const TAPS: usize = 4;
+/// Taps further apart than this start a new tempo
+const TAP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Metronome commands
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum MetronomeCommand {
+    /// Starts clicking, on the first beat of a bar
+    Start,
+    /// Stops clicking
+    Stop,
+    /// Changes the tempo, in quarter notes per minute
+    Tempo(u16),
+    /// Changes the time signature
+    TimeSignature(TimeSignature),
+    /// A tap of the tempo button at this instant, the clicks follow the
+    /// taps after the second one
+    Tap(Instant),
+}
+
+/// Channel used to send commands to a [`Metronome`]
+pub type MetronomeControl = Channel<CriticalSectionRawMutex, MetronomeCommand, 4>;
+
+/// Beat length measured from taps
+#[derive(Debug, Clone)]
+pub struct TapTempo {
+    last: Option<Instant>,
+    intervals: [Duration; TAPS],
+    count: usize,
+}
+
+impl TapTempo {
+    /// Creates a tap tempo without taps
+    pub const fn new() -> Self {
+        Self {
+            last: None,
+            intervals: [Duration::from_ticks(0); TAPS],
+            count: 0,
+        }
+    }
+
+    /// Registers a tap at `at`. Returns the mean interval of the last taps,
+    /// `None` for the first tap after a pause.
+    pub fn tap(&mut self, at: Instant) -> Option<Duration> {
+        let last = self.last.replace(at);
+        let interval = at.checked_duration_since(last?)?;
+        if interval > TAP_TIMEOUT {
+            self.count = 0;
+            return None;
+        }
+        self.intervals[self.count % TAPS] = interval;
+        self.count += 1;
+        let taps = self.count.min(TAPS);
+        let total = self.intervals[..taps]
+            .iter()
+            .fold(Duration::from_ticks(0), |total, &interval| total + interval);
+        Some(total / taps as u32)
+    }
+}
+
+impl Default for TapTempo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Metronome clicking on a buzzer and flashing an LED
+pub struct Metronome<'d> {
+    player: Player<'d>,
+    led: Output<'d>,
+    control: &'d MetronomeControl,
+    song: Song,
+    taps: TapTempo,
+}
+
+impl<'d> Metronome<'d> {
+    /// Creates a stopped metronome at the tempo and time signature of `song`
+    pub fn new(
+        player: Player<'d>,
+        led: Output<'d>,
+        control: &'d MetronomeControl,
+        song: Song,
+    ) -> Self {
+        Self {
+            player,
+            led,
+            control,
+            song,
+            taps: TapTempo::new(),
+        }
+    }
+
+    /// Returns the tempo and time signature
+    pub fn song(&self) -> Song {
+        self.song
+    }
+
+    /// Clicks while started, following the commands of the control channel
+    pub async fn run(&mut self) -> ! {
+        let mut running = false;
+        // Beat of the next click, 0 is the first beat of the bar
+        let mut beat = 0;
+        let mut next = Instant::now();
+        let mut last = next;
+        // End of the click that sounds
+        let mut click_end = None;
+        loop {
+            let deadline = match click_end {
+                Some(end) => end,
+                None if running => next,
+                None => Instant::MAX,
+            };
+            match select(Timer::at(deadline), self.control.receive()).await {
+                Either::First(_) => match click_end.take() {
+                    Some(_) => self.click_off(),
+                    None => {
+                        self.click_on(beat == 0);
+                        click_end = Some(next + CLICK);
+                        beat = (beat + 1) % self.song.time_signature().beats.max(1);
+                        last = next;
+                        next = last + self.song.beat_duration();
+                    }
+                },
+                Either::Second(MetronomeCommand::Start) => {
+                    if !running {
+                        running = true;
+                        beat = 0;
+                        next = Instant::now();
+                    }
+                }
+                Either::Second(MetronomeCommand::Stop) => {
+                    running = false;
+                    click_end = None;
+                    self.click_off();
+                }
+                Either::Second(MetronomeCommand::Tempo(tempo)) => {
+                    self.song.set_tempo(tempo.clamp(MIN_TEMPO, MAX_TEMPO));
+                    // The beat that started keeps its start
+                    next = last + self.song.beat_duration();
+                }
+                Either::Second(MetronomeCommand::TimeSignature(time_signature)) => {
+                    self.song.set_time_signature(time_signature);
+                    beat %= time_signature.beats.max(1);
+                    next = last + self.song.beat_duration();
+                }
+                Either::Second(MetronomeCommand::Tap(at)) => {
+                    if let Some(interval) = self.taps.tap(at) {
+                        let unit = self.song.time_signature().unit;
+                        self.song.set_tempo(tempo(interval, unit.divider()));
+                        // The next beat falls one beat after the tap
+                        last = at;
+                        next = at + self.song.beat_duration();
+                    }
+                }
+            }
+        }
+    }
+
+    fn click_on(&mut self, accent: bool) {
+        let _ = self.player.tone(if accent { ACCENT } else { BEAT });
+        self.led.set_high();
+    }
+
+    fn click_off(&mut self) {
+        self.player.silence();
+        self.led.set_low();
+    }
+}
+
+/// Returns the tempo in quarter notes per minute of beats of 1/`divider`
+/// note lasting `beat`
+fn tempo(beat: Duration, divider: u32) -> u16 {
+    let quarter_us = beat.as_micros().max(1) * divider as u64 / 4;
+    (60_000_000 / quarter_us.max(1)).clamp(MIN_TEMPO as u64, MAX_TEMPO as u64) as u16
+}
+
+/// Runs `metronome`
+#[embassy_executor::task]
+pub async fn metronome_task(mut metronome: Metronome<'static>) {
+    metronome.run().await
+}
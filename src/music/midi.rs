@@ -0,0 +1,527 @@
+//! Standard MIDI File (SMF) parser for monophonic playback
+//!
+//! Format 0 and 1 files are supported. The tracks are read in place from
+//! the file bytes (for example from `include_bytes!`), or one byte at a time
+//! from any [`TrackSource`], and merged in time order. Since the buzzer
+//! plays a single note at a time, the events are reduced to one line: at any
+//! moment the highest sounding note of the selected [`Voice`] plays.
+
+use embassy_time::Duration;
+use heapless::Vec;
+
+use super::REST;
+use super::pitch::{Pitch, Tuning};
+
+/// Maximum number of tracks of a format 1 file
+pub const MAX_TRACKS: usize = 16;
+
+/// Tempo used until the first tempo event, in microseconds per quarter note
+const DEFAULT_TEMPO: u32 = 500_000;
+
+/// Error returned when parsing a MIDI file
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum SmfError {
+    /// The data does not start with a MIDI header
+    InvalidHeader,
+    /// Format 2 and SMPTE time division are not supported
+    Unsupported,
+    /// The file has more than [`MAX_TRACKS`] tracks
+    TooManyTracks,
+    /// A chunk or an event goes past the end of the data
+    Truncated,
+    /// A data byte was found without a running status
+    MissingStatus,
+    /// The [`TrackSource`] could not read a track
+    Read,
+}
+
+/// Bytes of the tracks of a MIDI file, read one at a time
+pub trait TrackSource {
+    /// Returns the next byte of `track` (0 is the first track), `None` at
+    /// its end
+    fn next_byte(&mut self, track: usize) -> Result<Option<u8>, SmfError>;
+}
+
+/// Tracks read in place from the file bytes
+pub type TrackSlices<'a> = Vec<&'a [u8], MAX_TRACKS>;
+
+impl TrackSource for TrackSlices<'_> {
+    fn next_byte(&mut self, track: usize) -> Result<Option<u8>, SmfError> {
+        let Some((&byte, rest)) = self.get(track).copied().and_then(<[u8]>::split_first) else {
+            return Ok(None);
+        };
+        self[track] = rest;
+        Ok(Some(byte))
+    }
+}
+
+impl<S: TrackSource + ?Sized> TrackSource for &mut S {
+    fn next_byte(&mut self, track: usize) -> Result<Option<u8>, SmfError> {
+        (**self).next_byte(track)
+    }
+}
+
+/// Which notes make up the monophonic line
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum Voice {
+    /// Highest note of all channels and tracks
+    Highest,
+    /// Highest note of a MIDI channel (0 to 15)
+    Channel(u8),
+    /// Highest note of a track (0 is the first track)
+    Track(usize),
+}
+
+/// A note of the monophonic line
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub struct MidiNote {
+    /// The pitch, `None` for silence
+    pub pitch: Option<Pitch>,
+    /// How long the note lasts
+    pub duration: Duration,
+}
+
+impl MidiNote {
+    /// Returns the `(frequency, duration)` pair played by the melody player
+    pub fn to_timed_note(self, tuning: Tuning) -> (f64, Duration) {
+        let frequency = match self.pitch {
+            Some(pitch) => pitch.frequency(tuning),
+            None => REST,
+        };
+        (frequency, self.duration)
+    }
+}
+
+/// A parsed MIDI file
+#[derive(Debug, Clone)]
+pub struct Smf<'a> {
+    /// File format, 0 or 1
+    pub format: u16,
+    /// Ticks per quarter note
+    pub division: u16,
+    tracks: TrackSlices<'a>,
+}
+
+impl<'a> Smf<'a> {
+    /// Parses the header and locates the tracks of a MIDI file
+    pub fn parse(data: &'a [u8]) -> Result<Self, SmfError> {
+        let (id, header, mut rest) = chunk(data)?;
+        if id != b"MThd" {
+            return Err(SmfError::InvalidHeader);
+        }
+        let (format, division) = parse_header(header)?;
+
+        let mut tracks = Vec::new();
+        while !rest.is_empty() {
+            let (id, body, next) = chunk(rest)?;
+            // Unknown chunks have to be skipped
+            if id == b"MTrk" {
+                tracks.push(body).map_err(|_| SmfError::TooManyTracks)?;
+            }
+            rest = next;
+        }
+
+        Ok(Smf {
+            format,
+            division,
+            tracks,
+        })
+    }
+
+    /// Returns the number of tracks
+    pub fn track_count(&self) -> usize {
+        self.tracks.len()
+    }
+
+    /// Returns the monophonic line of `voice`
+    pub fn notes(&self, voice: Voice) -> MonoNotes<TrackSlices<'a>> {
+        MonoNotes::new(self.tracks.clone(), self.tracks.len(), self.division, voice)
+    }
+
+    /// Returns the monophonic line of `voice` as `(frequency, duration)`
+    /// pairs, stopping at the first malformed event
+    pub fn melody(
+        &self,
+        voice: Voice,
+        tuning: Tuning,
+    ) -> impl Iterator<Item = (f64, Duration)> + use<'a> {
+        self.notes(voice)
+            .map_while(Result::ok)
+            .map(move |note| note.to_timed_note(tuning))
+    }
+}
+
+/// Checks the body of an `MThd` chunk, returns the format and the ticks per
+/// quarter note
+pub(crate) fn parse_header(header: &[u8]) -> Result<(u16, u16), SmfError> {
+    if header.len() < 6 {
+        return Err(SmfError::InvalidHeader);
+    }
+    let format = u16::from_be_bytes([header[0], header[1]]);
+    let division = u16::from_be_bytes([header[4], header[5]]);
+    if format > 1 || division & 0x8000 != 0 || division == 0 {
+        return Err(SmfError::Unsupported);
+    }
+    Ok((format, division))
+}
+
+/// Id, body and the data after a chunk
+type Chunk<'a> = (&'a [u8], &'a [u8], &'a [u8]);
+
+/// Splits the chunk at the start of `data` into id, body and the rest
+fn chunk(data: &[u8]) -> Result<Chunk<'_>, SmfError> {
+    if data.len() < 8 {
+        return Err(SmfError::Truncated);
+    }
+    let len = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize;
+    // The length is untrusted and can overflow a 32-bit usize
+    let end = 8usize.checked_add(len).ok_or(SmfError::Truncated)?;
+    let body = data.get(8..end).ok_or(SmfError::Truncated)?;
+    Ok((&data[..4], body, &data[end..]))
+}
+
+/// Track events relevant for playback
+#[derive(Debug, Copy, Clone)]
+enum Event {
+    NoteOn { channel: u8, key: u8 },
+    NoteOff { channel: u8, key: u8 },
+    Tempo(u32),
+    Other,
+}
+
+/// Reads the events of a track one at a time
+struct TrackReader {
+    /// Index of the track in its [`TrackSource`]
+    index: usize,
+    tick: u64,
+    status: Option<u8>,
+    /// Tick of the next event, once its delta time has been read
+    pending: Option<u64>,
+    ended: bool,
+}
+
+impl TrackReader {
+    fn new(index: usize) -> Self {
+        Self {
+            index,
+            tick: 0,
+            status: None,
+            pending: None,
+            ended: false,
+        }
+    }
+
+    fn byte<S: TrackSource>(&self, source: &mut S) -> Result<u8, SmfError> {
+        source.next_byte(self.index)?.ok_or(SmfError::Truncated)
+    }
+
+    fn skip<S: TrackSource>(&self, source: &mut S, len: usize) -> Result<(), SmfError> {
+        for _ in 0..len {
+            self.byte(source)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a variable length quantity starting with `first`
+    fn vlq<S: TrackSource>(&self, source: &mut S, first: u8) -> Result<u32, SmfError> {
+        let mut byte = first;
+        let mut value = (byte & 0x7F) as u32;
+        for _ in 1..4 {
+            if byte & 0x80 == 0 {
+                break;
+            }
+            byte = self.byte(source)?;
+            value = (value << 7) | (byte & 0x7F) as u32;
+        }
+        Ok(value)
+    }
+
+    /// Returns the absolute tick of the next event, `None` at the end of
+    /// the track. Only the delta time is read, the event stays pending.
+    fn next_tick<S: TrackSource>(&mut self, source: &mut S) -> Option<Result<u64, SmfError>> {
+        if let Some(tick) = self.pending {
+            return Some(Ok(tick));
+        }
+        if self.ended {
+            return None;
+        }
+        let first = match source.next_byte(self.index) {
+            Ok(Some(byte)) => byte,
+            Ok(None) => {
+                self.ended = true;
+                return None;
+            }
+            Err(err) => return Some(Err(err)),
+        };
+        Some(self.vlq(source, first).map(|delta| {
+            self.tick += delta as u64;
+            self.pending = Some(self.tick);
+            self.tick
+        }))
+    }
+
+    /// Reads the event following the delta time returned by `next_tick`
+    fn event<S: TrackSource>(&mut self, source: &mut S) -> Result<Event, SmfError> {
+        self.pending = None;
+        let mut status = self.byte(source)?;
+        // With a running status, the byte is the first data byte
+        let mut first = None;
+        if status < 0x80 {
+            first = Some(status);
+            status = self.status.ok_or(SmfError::MissingStatus)?;
+        }
+
+        let channel = status & 0x0F;
+        match status {
+            0x80..=0xEF => {
+                self.status = Some(status);
+                let key = match first {
+                    Some(key) => key,
+                    None => self.byte(source)?,
+                };
+                if matches!(status & 0xF0, 0xC0 | 0xD0) {
+                    return Ok(Event::Other);
+                }
+                let velocity = self.byte(source)?;
+                Ok(match status & 0xF0 {
+                    0x90 if velocity > 0 => Event::NoteOn { channel, key },
+                    0x80 | 0x90 => Event::NoteOff { channel, key },
+                    _ => Event::Other,
+                })
+            }
+            0xFF => {
+                self.status = None;
+                let kind = self.byte(source)?;
+                let first = self.byte(source)?;
+                let len = self.vlq(source, first)? as usize;
+                match (kind, len) {
+                    (0x51, 3) => {
+                        let [a, b, c] =
+                            [self.byte(source)?, self.byte(source)?, self.byte(source)?];
+                        Ok(Event::Tempo(u32::from_be_bytes([0, a, b, c])))
+                    }
+                    // End of track, anything after it is ignored
+                    (0x2F, _) => {
+                        self.skip(source, len)?;
+                        self.ended = true;
+                        Ok(Event::Other)
+                    }
+                    _ => {
+                        self.skip(source, len)?;
+                        Ok(Event::Other)
+                    }
+                }
+            }
+            _ => {
+                // System exclusive
+                self.status = None;
+                let first = self.byte(source)?;
+                let len = self.vlq(source, first)? as usize;
+                self.skip(source, len)?;
+                Ok(Event::Other)
+            }
+        }
+    }
+}
+
+/// Iterator over the monophonic line of a MIDI file
+pub struct MonoNotes<S> {
+    source: S,
+    tracks: Vec<TrackReader, MAX_TRACKS>,
+    voice: Voice,
+    division: u64,
+    tempo: u64,
+    tick: u64,
+    time_us: u64,
+    remainder: u64,
+    /// Number of note on events of each key that are still sounding
+    active: [u8; 128],
+    current: Option<u8>,
+    start_us: u64,
+    done: bool,
+}
+
+impl<S: TrackSource> MonoNotes<S> {
+    /// Returns the monophonic line of `voice` from the first `tracks`
+    /// tracks of `source`, with `division` ticks per quarter note as
+    /// checked by the file header
+    pub fn new(source: S, tracks: usize, division: u16, voice: Voice) -> Self {
+        Self {
+            source,
+            tracks: (0..tracks.min(MAX_TRACKS)).map(TrackReader::new).collect(),
+            voice,
+            division: division.max(1) as u64,
+            tempo: DEFAULT_TEMPO as u64,
+            tick: 0,
+            time_us: 0,
+            remainder: 0,
+            active: [0; 128],
+            current: None,
+            start_us: 0,
+            done: false,
+        }
+    }
+
+    /// Returns the index of the track with the earliest pending event
+    fn next_track(&mut self) -> Result<Option<(usize, u64)>, SmfError> {
+        let mut next: Option<(usize, u64)> = None;
+        for (index, track) in self.tracks.iter_mut().enumerate() {
+            let Some(tick) = track.next_tick(&mut self.source) else {
+                continue;
+            };
+            let tick = tick?;
+            if next.is_none_or(|(_, earliest)| tick < earliest) {
+                next = Some((index, tick));
+            }
+        }
+        Ok(next)
+    }
+
+    fn highest(&self) -> Option<u8> {
+        (0..128u8).rev().find(|&key| self.active[key as usize] > 0)
+    }
+
+    fn segment(&mut self) -> Option<MidiNote> {
+        let duration = self.time_us - self.start_us;
+        let pitch = self.current.and_then(Pitch::from_midi);
+        self.start_us = self.time_us;
+        (duration > 0).then(|| MidiNote {
+            pitch,
+            duration: Duration::from_micros(duration),
+        })
+    }
+}
+
+impl<S: TrackSource> Iterator for MonoNotes<S> {
+    type Item = Result<MidiNote, SmfError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let (index, tick) = match self.next_track() {
+                Ok(Some(next)) => next,
+                Ok(None) => {
+                    self.done = true;
+                    return self.segment().map(Ok);
+                }
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            };
+
+            // Convert the ticks since the previous event, keeping the
+            // remainder so tempo changes do not accumulate rounding errors
+            let elapsed = (tick - self.tick) * self.tempo + self.remainder;
+            self.time_us += elapsed / self.division;
+            self.remainder = elapsed % self.division;
+            self.tick = tick;
+
+            let event = match self.tracks[index].event(&mut self.source) {
+                Ok(event) => event,
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            };
+
+            let selected = |channel: u8| match self.voice {
+                Voice::Highest => true,
+                Voice::Channel(selected) => channel == selected,
+                Voice::Track(selected) => index == selected,
+            };
+            match event {
+                Event::NoteOn { channel, key } if selected(channel) => {
+                    let count = &mut self.active[key as usize & 0x7F];
+                    *count = count.saturating_add(1);
+                }
+                Event::NoteOff { channel, key } if selected(channel) => {
+                    let count = &mut self.active[key as usize & 0x7F];
+                    *count = count.saturating_sub(1);
+                }
+                Event::Tempo(tempo) => self.tempo = tempo as u64,
+                _ => {}
+            }
+
+            let highest = self.highest();
+            if highest != self.current {
+                let note = self.segment();
+                self.current = highest;
+                if let Some(note) = note {
+                    return Some(Ok(note));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Format 1 file at 96 ticks per quarter note: a tempo track, and C4 then
+    /// E4 for a quarter note each, with running status and a system
+    /// exclusive event in between
+    const TWO_TRACKS: &[u8] = &[
+        b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 1, 0, 2, 0, 96, //
+        b'M', b'T', b'r', b'k', 0, 0, 0, 11, //
+        0x00, 0xFF, 0x51, 3, 0x07, 0xA1, 0x20, 0x00, 0xFF, 0x2F, 0, //
+        b'M', b'T', b'r', b'k', 0, 0, 0, 23, //
+        0x00, 0x90, 60, 64, 0x60, 60, 0, 0x00, 64, 64, //
+        0x00, 0xF0, 2, 0x7E, 0xF7, 0x60, 0x80, 64, 0, 0x00, 0xFF, 0x2F, 0,
+    ];
+
+    /// Fails to read after `left` bytes of any track
+    struct Failing {
+        tracks: TrackSlices<'static>,
+        left: usize,
+    }
+
+    impl TrackSource for Failing {
+        fn next_byte(&mut self, track: usize) -> Result<Option<u8>, SmfError> {
+            self.left = self.left.checked_sub(1).ok_or(SmfError::Read)?;
+            self.tracks.next_byte(track)
+        }
+    }
+
+    fn note(name: &str, millis: u64) -> Result<MidiNote, SmfError> {
+        Ok(MidiNote {
+            pitch: name.parse().ok(),
+            duration: Duration::from_millis(millis),
+        })
+    }
+
+    #[test]
+    fn merges_tracks() {
+        let smf = Smf::parse(TWO_TRACKS).unwrap();
+        assert_eq!((smf.format, smf.division, smf.track_count()), (1, 96, 2));
+        let notes: Vec<_, 4> = smf.notes(Voice::Highest).collect();
+        assert_eq!(&notes[..], &[note("C4", 500), note("E4", 500)]);
+        // The tempo track has no notes, it is silent for as long
+        let tempo_track: Vec<_, 4> = smf.notes(Voice::Track(0)).collect();
+        assert_eq!(&tempo_track[..], &[note("R", 1000)]);
+    }
+
+    #[test]
+    fn invalid_chunks() {
+        assert_eq!(Smf::parse(&TWO_TRACKS[..40]).unwrap_err(), SmfError::Truncated);
+        let mut format_2 = [0; 14];
+        format_2.copy_from_slice(&TWO_TRACKS[..14]);
+        format_2[9] = 2;
+        assert_eq!(Smf::parse(&format_2).unwrap_err(), SmfError::Unsupported);
+        assert_eq!(Smf::parse(b"RIFF\0\0\0\0").unwrap_err(), SmfError::InvalidHeader);
+    }
+
+    #[test]
+    fn read_errors_end_the_notes() {
+        let smf = Smf::parse(TWO_TRACKS).unwrap();
+        let source = Failing {
+            tracks: smf.tracks.clone(),
+            left: 20,
+        };
+        let mut notes = MonoNotes::new(source, 2, smf.division, Voice::Highest);
+        assert_eq!(notes.next(), Some(note("C4", 500)));
+        assert_eq!(notes.next(), Some(Err(SmfError::Read)));
+        assert_eq!(notes.next(), None);
+    }
+}
@@ -0,0 +1,416 @@
+//! Music Macro Language (MML) interpreter
+//!
+//! MML writes a melody as a string of commands, for example
+//! `T120 O4 L8 CDEFGAB>C`. Commands are not case sensitive and spaces are
+//! ignored:
+//!
+//! * `A` to `G` play a note, followed by `+` or `#` for sharp or `-` for
+//!   flat, an optional length and dots, and `&` to tie it to the next note
+//! * `N` plays a MIDI note number, like `N60` for middle C
+//! * `R` (or `P`) is a rest, with an optional length and dots
+//! * `O` sets the octave (4 is the octave of middle C), `>` and `<` move
+//!   one octave up or down
+//! * `L` sets the default length, `T` the tempo in quarter notes per minute
+//! * `[` ... `]n` repeats the commands between brackets `n` times, twice
+//!   without a number, loops can be nested
+//!
+//! Lengths are dividers of a whole note, 4 is a quarter note. Multiples of 3
+//! are triplets, 12 is an eighth note triplet.
+//!
+//! The whole string is validated by [`Mml::parse`], so the events are
+//! produced afterwards without allocation. Loops are validated once, a note
+//! that repetitions move out of range with `>` or `<` ends the melody.
+
+use heapless::Vec;
+
+use super::Song;
+use super::envelope::Articulation;
+use super::pitch::{Accidental, NoteName, Pitch, Tuning};
+use super::rhythm::{Event, NoteLength, NoteValue};
+
+/// Maximum nesting of loops
+pub const MAX_DEPTH: usize = 4;
+
+/// Tempo until the first `T` command
+const DEFAULT_TEMPO: u16 = 120;
+/// Octave until the first `O` command
+const DEFAULT_OCTAVE: i8 = 4;
+/// Length until the first `L` command
+const DEFAULT_LENGTH: u16 = 4;
+/// Repetitions of a loop without a count
+const DEFAULT_REPEAT: u16 = 2;
+
+/// Error returned when parsing MML, with the byte offset of the command
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum MmlError {
+    /// The command is unknown
+    UnknownCommand(usize),
+    /// The value of the command is missing or invalid
+    InvalidValue(usize),
+    /// The note is outside of the MIDI range
+    OutOfRange(usize),
+    /// A `[` has no `]` or a `]` has no `[`
+    UnbalancedLoop(usize),
+    /// Loops are nested deeper than [`MAX_DEPTH`]
+    TooDeep(usize),
+}
+
+/// A validated MML melody
+#[derive(Debug, Copy, Clone)]
+pub struct Mml<'a> {
+    source: &'a str,
+}
+
+impl<'a> Mml<'a> {
+    /// Parses and validates an MML melody
+    pub fn parse(source: &'a str) -> Result<Self, MmlError> {
+        let mut events = MmlEvents::new(source, Tuning::STANDARD);
+        // Loops are checked once instead of being repeated
+        events.repeat = false;
+        while events.step()?.is_some() {}
+        Ok(Self { source })
+    }
+
+    /// Returns the song timing before the first `T` command
+    pub fn song(&self) -> Song {
+        Song::new(DEFAULT_TEMPO)
+    }
+
+    /// Returns the events of the melody, with loops repeated
+    pub fn events(&self, tuning: Tuning) -> MmlEvents<'a> {
+        MmlEvents::new(self.source, tuning)
+    }
+}
+
+/// A loop being played
+#[derive(Debug, Copy, Clone)]
+struct Loop {
+    /// Offset after the `[`
+    start: usize,
+    /// Repetitions left, `None` until the `]` is first reached
+    remaining: Option<u16>,
+}
+
+/// Events of an MML melody, returned by [`Mml::events`]
+#[derive(Debug, Clone)]
+pub struct MmlEvents<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    tuning: Tuning,
+    octave: i8,
+    /// Default length divider and dots
+    length: (u16, u8),
+    loops: Vec<Loop, MAX_DEPTH>,
+    repeat: bool,
+}
+
+impl<'a> MmlEvents<'a> {
+    fn new(source: &'a str, tuning: Tuning) -> Self {
+        Self {
+            bytes: source.as_bytes(),
+            pos: 0,
+            tuning,
+            octave: DEFAULT_OCTAVE,
+            length: (DEFAULT_LENGTH, 0),
+            loops: Vec::new(),
+            repeat: true,
+        }
+    }
+
+    /// Interprets commands until the next event, `None` at the end
+    fn step(&mut self) -> Result<Option<Event>, MmlError> {
+        loop {
+            self.skip_spaces();
+            let start = self.pos;
+            let Some(&command) = self.bytes.get(self.pos) else {
+                return match self.loops.last() {
+                    Some(open) => Err(MmlError::UnbalancedLoop(open.start - 1)),
+                    None => Ok(None),
+                };
+            };
+            self.pos += 1;
+            match command.to_ascii_uppercase() {
+                letter @ b'A'..=b'G' => {
+                    let name = match letter {
+                        b'C' => NoteName::C,
+                        b'D' => NoteName::D,
+                        b'E' => NoteName::E,
+                        b'F' => NoteName::F,
+                        b'G' => NoteName::G,
+                        b'A' => NoteName::A,
+                        _ => NoteName::B,
+                    };
+                    let accidental = match self.peek() {
+                        Some(b'+' | b'#') => Accidental::Sharp,
+                        Some(b'-') => Accidental::Flat,
+                        _ => Accidental::Natural,
+                    };
+                    if accidental != Accidental::Natural {
+                        self.pos += 1;
+                    }
+                    let pitch = Pitch::new(name, accidental, self.octave)
+                        .ok_or(MmlError::OutOfRange(start))?;
+                    return self.note(pitch, start).map(Some);
+                }
+                b'N' => {
+                    let pitch = self
+                        .number()
+                        .and_then(|midi| u8::try_from(midi).ok())
+                        .and_then(Pitch::from_midi)
+                        .ok_or(MmlError::InvalidValue(start))?;
+                    return self.note(pitch, start).map(Some);
+                }
+                b'R' | b'P' => {
+                    let duration = self.duration(start)?;
+                    return Ok(Some(Event::Rest(duration)));
+                }
+                b'O' => {
+                    self.octave =
+                        self.number()
+                            .filter(|&octave| octave <= 8)
+                            .ok_or(MmlError::InvalidValue(start))? as i8;
+                }
+                b'>' => self.octave = self.octave.saturating_add(1),
+                b'<' => self.octave = self.octave.saturating_sub(1),
+                b'L' => {
+                    let divider = self.number().ok_or(MmlError::InvalidValue(start))?;
+                    let dots = self.dots();
+                    to_duration(divider, dots).ok_or(MmlError::InvalidValue(start))?;
+                    self.length = (divider, dots);
+                }
+                b'T' => {
+                    let tempo = self
+                        .number()
+                        .filter(|&tempo| tempo > 0)
+                        .ok_or(MmlError::InvalidValue(start))?;
+                    return Ok(Some(Event::Tempo(tempo)));
+                }
+                b'[' => {
+                    self.loops
+                        .push(Loop {
+                            start: self.pos,
+                            remaining: None,
+                        })
+                        .map_err(|_| MmlError::TooDeep(start))?;
+                }
+                b']' => {
+                    let count = match self.number() {
+                        Some(0) => return Err(MmlError::InvalidValue(start)),
+                        Some(count) => count,
+                        None => DEFAULT_REPEAT,
+                    };
+                    let open = self
+                        .loops
+                        .last_mut()
+                        .ok_or(MmlError::UnbalancedLoop(start))?;
+                    let remaining = open.remaining.unwrap_or(count - 1);
+                    if self.repeat && remaining > 0 {
+                        open.remaining = Some(remaining - 1);
+                        self.pos = open.start;
+                    } else {
+                        self.loops.pop();
+                    }
+                }
+                _ => return Err(MmlError::UnknownCommand(start)),
+            }
+        }
+    }
+
+    /// Reads the length and tie of a note
+    fn note(&mut self, pitch: Pitch, start: usize) -> Result<Event, MmlError> {
+        let duration = self.duration(start)?;
+        let tie = self.peek() == Some(b'&');
+        if tie {
+            self.pos += 1;
+        }
+        Ok(Event::Note {
+            frequency: pitch.frequency(self.tuning),
+            duration,
+            tie,
+            articulation: Articulation::Normal,
+        })
+    }
+
+    /// Reads an optional length and dots, the default length is used
+    /// without a number
+    fn duration(&mut self, start: usize) -> Result<NoteLength, MmlError> {
+        let (divider, dots) = match self.number() {
+            Some(divider) => (divider, self.dots()),
+            None => (self.length.0, self.length.1.saturating_add(self.dots())),
+        };
+        to_duration(divider, dots).ok_or(MmlError::InvalidValue(start))
+    }
+
+    /// Reads a decimal number, `None` if there is none
+    fn number(&mut self) -> Option<u16> {
+        self.skip_spaces();
+        let start = self.pos;
+        let mut value: u16 = 0;
+        while let Some(digit) = self.peek().filter(u8::is_ascii_digit) {
+            value = value
+                .saturating_mul(10)
+                .saturating_add((digit - b'0') as u16);
+            self.pos += 1;
+        }
+        (self.pos > start).then_some(value)
+    }
+
+    /// Reads dots and returns how many there are
+    fn dots(&mut self) -> u8 {
+        let mut dots: u8 = 0;
+        while self.peek() == Some(b'.') {
+            dots = dots.saturating_add(1);
+            self.pos += 1;
+        }
+        dots
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(|byte| byte.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+}
+
+impl Iterator for MmlEvents<'_> {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        // The melody was validated by `Mml::parse`
+        self.step().ok().flatten()
+    }
+}
+
+/// Returns the duration of a length divider with `dots`, multiples of 3 are
+/// triplets
+fn to_duration(divider: u16, dots: u8) -> Option<NoteLength> {
+    if let Some(value) = NoteValue::from_divider(divider as u32) {
+        return match dots {
+            0 => Some(NoteLength::Plain(value)),
+            1 => Some(NoteLength::Dotted(value)),
+            2 => Some(NoteLength::DoubleDotted(value)),
+            _ => None,
+        };
+    }
+    // A triplet of the next longer value, 12 is 3 in the time of 2 eighths
+    if divider % 3 != 0 || dots > 0 {
+        return None;
+    }
+    NoteValue::from_divider(divider as u32 / 3 * 2).map(NoteLength::triplet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::{NOTE_C3, NOTE_C4, NOTE_C5, NOTE_CS4, NOTE_D4, NOTE_E4, NOTE_G4, frequency};
+
+    /// Returns all events of a valid melody
+    fn events(source: &str) -> Vec<Event, 32> {
+        Mml::parse(source)
+            .unwrap()
+            .events(Tuning::STANDARD)
+            .collect()
+    }
+
+    /// Returns the frequencies of the notes of a valid melody
+    fn frequencies(source: &str) -> Vec<f64, 32> {
+        events(source)
+            .iter()
+            .filter_map(|event| match *event {
+                Event::Note { frequency, .. } => Some(frequency),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn notes_and_accidentals() {
+        assert_eq!(
+            events("T90 c4 C+ D-8 n64"),
+            [
+                Event::Tempo(90),
+                Event::note(NOTE_C4, NoteLength::Plain(NoteValue::Quarter)),
+                Event::note(NOTE_CS4, NoteLength::Plain(NoteValue::Quarter)),
+                Event::note(NOTE_CS4, NoteLength::Plain(NoteValue::Eighth)),
+                Event::note(NOTE_E4, NoteLength::Plain(NoteValue::Quarter)),
+            ]
+        );
+    }
+
+    #[test]
+    fn octave_shifts() {
+        assert_eq!(frequencies("C > C < < C O4 C"), [NOTE_C4, NOTE_C5, NOTE_C3, NOTE_C4]);
+        assert_eq!(frequencies("O3 C >> C"), [NOTE_C3, NOTE_C5]);
+    }
+
+    #[test]
+    fn dotted_and_triplet_lengths() {
+        assert_eq!(
+            events("L8 C C. C.. C12 R16. L4. D D. E2"),
+            [
+                Event::note(NOTE_C4, NoteLength::Plain(NoteValue::Eighth)),
+                Event::note(NOTE_C4, NoteLength::Dotted(NoteValue::Eighth)),
+                Event::note(NOTE_C4, NoteLength::DoubleDotted(NoteValue::Eighth)),
+                Event::note(NOTE_C4, NoteLength::triplet(NoteValue::Eighth)),
+                Event::Rest(NoteLength::Dotted(NoteValue::Sixteenth)),
+                // The default length keeps its dot, more dots are added
+                Event::note(NOTE_D4, NoteLength::Dotted(NoteValue::Quarter)),
+                Event::note(NOTE_D4, NoteLength::DoubleDotted(NoteValue::Quarter)),
+                Event::note(NOTE_E4, NoteLength::Plain(NoteValue::Half)),
+            ]
+        );
+    }
+
+    #[test]
+    fn ties() {
+        let events = events("G2&G8 G");
+        let ties: Vec<bool, 3> = events
+            .iter()
+            .map(|event| matches!(event, Event::Note { tie: true, .. }))
+            .collect();
+        assert_eq!(ties, [true, false, false]);
+        assert_eq!(
+            events[1],
+            Event::Note {
+                frequency: NOTE_G4,
+                duration: NoteLength::Plain(NoteValue::Eighth),
+                tie: false,
+                articulation: Articulation::Normal,
+            }
+        );
+    }
+
+    #[test]
+    fn nested_loops() {
+        let (c, d, e) = (NOTE_C4, NOTE_D4, NOTE_E4);
+        assert_eq!(frequencies("[C [D]3 ]2 E"), [c, d, d, d, c, d, d, d, e]);
+        assert_eq!(frequencies("[C]"), [c, c]);
+        // Octave changes carry over to the next repetition
+        assert_eq!(frequencies("O4 [C >]3"), [NOTE_C4, NOTE_C5, frequency("C6")]);
+    }
+
+    #[test]
+    fn error_offsets() {
+        assert_eq!(Mml::parse("C D X").unwrap_err(), MmlError::UnknownCommand(4));
+        assert_eq!(Mml::parse("C O9").unwrap_err(), MmlError::InvalidValue(2));
+        assert_eq!(Mml::parse("L5 C").unwrap_err(), MmlError::InvalidValue(0));
+        assert_eq!(Mml::parse("C N128").unwrap_err(), MmlError::InvalidValue(2));
+        assert_eq!(Mml::parse("T0").unwrap_err(), MmlError::InvalidValue(0));
+        assert_eq!(Mml::parse("C [D]0").unwrap_err(), MmlError::InvalidValue(4));
+        assert_eq!(Mml::parse("O8>>C").unwrap_err(), MmlError::OutOfRange(4));
+        assert_eq!(Mml::parse("C [D").unwrap_err(), MmlError::UnbalancedLoop(2));
+        assert_eq!(Mml::parse("C D]").unwrap_err(), MmlError::UnbalancedLoop(3));
+        assert_eq!(Mml::parse("[[[[[C]]]]]").unwrap_err(), MmlError::TooDeep(4));
+    }
+
+    #[test]
+    fn dots_saturate() {
+        let mut events = MmlEvents::new("C.", Tuning::STANDARD);
+        events.length = (4, u8::MAX);
+        assert_eq!(events.step(), Err(MmlError::InvalidValue(0)));
+    }
+}
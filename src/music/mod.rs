@@ -0,0 +1,256 @@
+#[cfg(not(feature = "std"))]
+pub mod buzzer;
+pub mod effects;
+pub mod envelope;
+pub mod json;
+pub mod library;
+#[cfg(not(feature = "std"))]
+pub mod lights;
+pub mod melody;
+#[cfg(not(feature = "std"))]
+pub mod metronome;
+pub mod midi;
+pub mod mml;
+pub mod pitch;
+#[cfg(not(feature = "std"))]
+pub mod player;
+pub mod rhythm;
+pub mod rtttl;
+#[cfg(not(feature = "std"))]
+pub mod sdcard;
+#[cfg(not(feature = "std"))]
+pub mod sequencer;
+pub mod sounds;
+#[cfg(not(feature = "std"))]
+pub mod synth;
+pub mod tone;
+pub mod transform;
+pub mod tuner;
+#[cfg(feature = "std")]
+pub mod wav;
+
+#[cfg(not(feature = "std"))]
+pub use buzzer::Buzzer;
+pub use effects::{Effect, Segment};
+pub use envelope::{Articulation, Envelope};
+pub use json::{JsonNote, JsonSong, JsonSongError};
+#[cfg(not(feature = "std"))]
+pub use lights::{light_task, RgbLed};
+pub use melody::Melody;
+#[cfg(not(feature = "std"))]
+pub use metronome::{metronome_task, Metronome, MetronomeCommand, MetronomeControl, TapTempo};
+pub use midi::{MidiNote, Smf, SmfError, TrackSource, Voice};
+pub use mml::{Mml, MmlError};
+pub use pitch::{
+    frequency, semitone_ratio, Accidental, Key, Mode, NoteName, ParsePitchError, Pitch, Tuning,
+};
+#[cfg(not(feature = "std"))]
+pub use player::{player_task, Command, NoteChannel, NoteEvent, Player, PlayerControl, Stopped};
+pub use rhythm::{Event, NoteLength, NoteValue, TimeSignature};
+pub use rtttl::{Rtttl, RtttlError, RtttlNote};
+#[cfg(not(feature = "std"))]
+pub use sdcard::{LoadError, MidiTracks, SongFile, SongFormat, SongLoader};
+#[cfg(not(feature = "std"))]
+pub use sequencer::{NoteCursor, Score, Sequencer};
+#[cfg(not(feature = "std"))]
+pub use sounds::sound_task;
+pub use sounds::{MusicEvent, MusicState, Priority, Sounds};
+#[cfg(not(feature = "std"))]
+pub use synth::{Oscillator, PwmDac, Synth, Waveform};
+pub use tone::{Tone, ToneError};
+pub use tuner::{Reading, Yin};
+
+#[allow(unused)]
+// Note frequencies in Hertz as f64, equal temperament with A4 = 440 Hz
+pub const NOTE_B0: f64 = frequency("B0");
+pub const NOTE_C1: f64 = frequency("C1");
+pub const NOTE_CS1: f64 = frequency("C#1");
+pub const NOTE_D1: f64 = frequency("D1");
+pub const NOTE_DS1: f64 = frequency("D#1");
+pub const NOTE_E1: f64 = frequency("E1");
+pub const NOTE_F1: f64 = frequency("F1");
+pub const NOTE_FS1: f64 = frequency("F#1");
+pub const NOTE_G1: f64 = frequency("G1");
+pub const NOTE_GS1: f64 = frequency("G#1");
+pub const NOTE_A1: f64 = frequency("A1");
+pub const NOTE_AS1: f64 = frequency("A#1");
+pub const NOTE_B1: f64 = frequency("B1");
+pub const NOTE_C2: f64 = frequency("C2");
+pub const NOTE_CS2: f64 = frequency("C#2");
+pub const NOTE_D2: f64 = frequency("D2");
+pub const NOTE_DS2: f64 = frequency("D#2");
+pub const NOTE_E2: f64 = frequency("E2");
+pub const NOTE_F2: f64 = frequency("F2");
+pub const NOTE_FS2: f64 = frequency("F#2");
+pub const NOTE_G2: f64 = frequency("G2");
+pub const NOTE_GS2: f64 = frequency("G#2");
+pub const NOTE_A2: f64 = frequency("A2");
+pub const NOTE_AS2: f64 = frequency("A#2");
+pub const NOTE_B2: f64 = frequency("B2");
+pub const NOTE_C3: f64 = frequency("C3");
+pub const NOTE_CS3: f64 = frequency("C#3");
+pub const NOTE_D3: f64 = frequency("D3");
+pub const NOTE_DS3: f64 = frequency("D#3");
+pub const NOTE_E3: f64 = frequency("E3");
+pub const NOTE_F3: f64 = frequency("F3");
+pub const NOTE_FS3: f64 = frequency("F#3");
+pub const NOTE_G3: f64 = frequency("G3");
+pub const NOTE_GS3: f64 = frequency("G#3");
+pub const NOTE_A3: f64 = frequency("A3");
+pub const NOTE_AS3: f64 = frequency("A#3");
+pub const NOTE_B3: f64 = frequency("B3");
+pub const NOTE_C4: f64 = frequency("C4");
+pub const NOTE_CS4: f64 = frequency("C#4");
+pub const NOTE_D4: f64 = frequency("D4");
+pub const NOTE_DS4: f64 = frequency("D#4");
+pub const NOTE_E4: f64 = frequency("E4");
+pub const NOTE_F4: f64 = frequency("F4");
+pub const NOTE_FS4: f64 = frequency("F#4");
+pub const NOTE_G4: f64 = frequency("G4");
+pub const NOTE_GS4: f64 = frequency("G#4");
+pub const NOTE_A4: f64 = frequency("A4");
+pub const NOTE_AS4: f64 = frequency("A#4");
+pub const NOTE_B4: f64 = frequency("B4");
+pub const NOTE_C5: f64 = frequency("C5");
+pub const NOTE_CS5: f64 = frequency("C#5");
+pub const NOTE_D5: f64 = frequency("D5");
+pub const NOTE_DS5: f64 = frequency("D#5");
+pub const NOTE_E5: f64 = frequency("E5");
+pub const NOTE_F5: f64 = frequency("F5");
+pub const NOTE_FS5: f64 = frequency("F#5");
+pub const NOTE_G5: f64 = frequency("G5");
+pub const NOTE_GS5: f64 = frequency("G#5");
+pub const NOTE_A5: f64 = frequency("A5");
+pub const NOTE_AS5: f64 = frequency("A#5");
+pub const NOTE_B5: f64 = frequency("B5");
+pub const NOTE_C6: f64 = frequency("C6");
+pub const NOTE_CS6: f64 = frequency("C#6");
+pub const NOTE_D6: f64 = frequency("D6");
+pub const NOTE_DS6: f64 = frequency("D#6");
+pub const NOTE_E6: f64 = frequency("E6");
+pub const NOTE_F6: f64 = frequency("F6");
+pub const NOTE_FS6: f64 = frequency("F#6");
+pub const NOTE_G6: f64 = frequency("G6");
+pub const NOTE_GS6: f64 = frequency("G#6");
+pub const NOTE_A6: f64 = frequency("A6");
+pub const NOTE_AS6: f64 = frequency("A#6");
+pub const NOTE_B6: f64 = frequency("B6");
+pub const NOTE_C7: f64 = frequency("C7");
+pub const NOTE_CS7: f64 = frequency("C#7");
+pub const NOTE_D7: f64 = frequency("D7");
+pub const NOTE_DS7: f64 = frequency("D#7");
+pub const NOTE_E7: f64 = frequency("E7");
+pub const NOTE_F7: f64 = frequency("F7");
+pub const NOTE_FS7: f64 = frequency("F#7");
+pub const NOTE_G7: f64 = frequency("G7");
+pub const NOTE_GS7: f64 = frequency("G#7");
+pub const NOTE_A7: f64 = frequency("A7");
+pub const NOTE_AS7: f64 = frequency("A#7");
+pub const NOTE_B7: f64 = frequency("B7");
+pub const NOTE_C8: f64 = frequency("C8");
+pub const NOTE_CS8: f64 = frequency("C#8");
+pub const NOTE_D8: f64 = frequency("D8");
+pub const NOTE_DS8: f64 = frequency("D#8");
+pub const REST: f64 = 0.0; // No sound, for pauses
+
+/// Length of a whole note at 1 quarter note per minute, in microseconds
+const WHOLE_NOTE_US: u64 = 60_000_000 * 4;
+
+#[derive(Clone, Copy)]
+pub struct Song {
+    whole_note: u32,
+    tempo: u16,
+    time_signature: TimeSignature,
+}
+
+fn main() {
+    // Example usage of the Song struct
+    let song = Song::new(120);
+    let duration = song.calc_note_duration(4);
+    // Print the duration of a quarter note
+    // In a no_std environment, you might want to use a different method to output this value
+    // For example, sending it over a serial connection or using a debug interface
+    // Here, we just use a placeholder comment
+    // println!("Duration of a quarter note: {}", duration);
+}
+
+impl Song {
+    pub const fn new(tempo: u16) -> Self {
+        // A tempo of 0 would never end a note
+        let tempo = if tempo == 0 { 1 } else { tempo };
+        let whole_note = (60_000 * 4) / tempo as u32;
+        Self {
+            whole_note,
+            tempo,
+            time_signature: TimeSignature::COMMON,
+        }
+    }
+
+    /// Returns the song with another time signature
+    pub const fn with_time_signature(mut self, time_signature: TimeSignature) -> Self {
+        self.time_signature = time_signature;
+        self
+    }
+
+    /// Returns the tempo, in quarter notes per minute
+    pub fn tempo(&self) -> u16 {
+        self.tempo
+    }
+
+    /// Changes the tempo, in quarter notes per minute
+    pub fn set_tempo(&mut self, tempo: u16) {
+        *self = Self::new(tempo).with_time_signature(self.time_signature);
+    }
+
+    /// Returns the time signature
+    pub fn time_signature(&self) -> TimeSignature {
+        self.time_signature
+    }
+
+    /// Changes the time signature
+    pub fn set_time_signature(&mut self, time_signature: TimeSignature) {
+        self.time_signature = time_signature;
+    }
+
+    /// Returns the length of `duration` at the current tempo, rounded to
+    /// the microsecond instead of the millisecond
+    pub fn duration(&self, duration: NoteLength) -> embassy_time::Duration {
+        self.fraction_duration(duration.fraction())
+    }
+
+    /// Returns the length of a divider like [`Song::calc_note_duration`],
+    /// rounded to the microsecond
+    pub fn divider_duration(&self, divider: i16) -> embassy_time::Duration {
+        let den = divider.unsigned_abs().max(1) as u32;
+        if divider > 0 {
+            self.fraction_duration((1, den))
+        } else {
+            self.fraction_duration((3, 2 * den))
+        }
+    }
+
+    /// Returns the length of a bar
+    pub fn bar_duration(&self) -> embassy_time::Duration {
+        self.fraction_duration(self.time_signature.bar_fraction())
+    }
+
+    /// Returns the length of a beat
+    pub fn beat_duration(&self) -> embassy_time::Duration {
+        self.fraction_duration((1, self.time_signature.unit.divider()))
+    }
+
+    fn fraction_duration(&self, (num, den): (u32, u32)) -> embassy_time::Duration {
+        let us = WHOLE_NOTE_US * num as u64 / (self.tempo.max(1) as u64 * den as u64);
+        embassy_time::Duration::from_micros(us)
+    }
+
+    pub fn calc_note_duration(&self, divider: i16) -> u32 {
+        if divider > 0 {
+            self.whole_note / divider as u32
+        } else {
+            let duration = self.whole_note / divider.unsigned_abs() as u32;
+            (duration as f64 * 1.5) as u32
+        }
+    }
+}
+
@@ -0,0 +1,429 @@
+//! Equal temperament pitches
+//!
+//! A [`Pitch`] is stored as its MIDI note number (C4 = 60, A4 = 69) and its
+//! frequency is computed from the A4 reference of a [`Tuning`]. Pitches can
+//! be built from a note name, accidental and octave, or parsed from strings
+//! like `"C#4"` or `"Bb3"`. All of this works in `const` context, which is
+//! how the `NOTE_*` constants are derived.
+
+use core::fmt;
+use core::str::FromStr;
+
+/// 2^(n/12) for the 12 semitones of an octave
+const SEMITONE_RATIOS: [f64; 12] = [
+    1.0,
+    1.059_463_094_359_295_3,
+    1.122_462_048_309_373,
+    1.189_207_115_002_721,
+    1.259_921_049_894_873_2,
+    1.334_839_854_170_034_4,
+    core::f64::consts::SQRT_2,
+    1.498_307_076_876_681_5,
+    1.587_401_051_968_199_5,
+    1.681_792_830_507_429,
+    1.781_797_436_280_678_5,
+    1.887_748_625_363_386_9,
+];
+
+/// MIDI number of A4
+const A4_MIDI: i32 = 69;
+
+/// 2^(1/24), the ratio of half a semitone
+const HALF_SEMITONE: f64 = 1.029_302_236_643_492;
+
+/// Note names of the 12 semitones, spelled with sharps
+const SEMITONE_NAMES: [(NoteName, Accidental); 12] = [
+    (NoteName::C, Accidental::Natural),
+    (NoteName::C, Accidental::Sharp),
+    (NoteName::D, Accidental::Natural),
+    (NoteName::D, Accidental::Sharp),
+    (NoteName::E, Accidental::Natural),
+    (NoteName::F, Accidental::Natural),
+    (NoteName::F, Accidental::Sharp),
+    (NoteName::G, Accidental::Natural),
+    (NoteName::G, Accidental::Sharp),
+    (NoteName::A, Accidental::Natural),
+    (NoteName::A, Accidental::Sharp),
+    (NoteName::B, Accidental::Natural),
+];
+
+/// Reference frequency of the tuning
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Tuning {
+    /// Frequency of A4, in Hz
+    pub a4: f64,
+}
+
+impl Tuning {
+    /// A4 = 440 Hz
+    pub const STANDARD: Tuning = Tuning { a4: 440.0 };
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Self::STANDARD
+    }
+}
+
+/// Natural note names
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum NoteName {
+    C,
+    D,
+    E,
+    F,
+    G,
+    A,
+    B,
+}
+
+impl NoteName {
+    /// Semitones above C
+    pub const fn semitone(self) -> i32 {
+        match self {
+            NoteName::C => 0,
+            NoteName::D => 2,
+            NoteName::E => 4,
+            NoteName::F => 5,
+            NoteName::G => 7,
+            NoteName::A => 9,
+            NoteName::B => 11,
+        }
+    }
+}
+
+/// Accidentals
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum Accidental {
+    /// No accidental
+    Natural,
+    /// One semitone up (`#`)
+    Sharp,
+    /// One semitone down (`b`)
+    Flat,
+}
+
+impl Accidental {
+    /// Semitone offset
+    pub const fn offset(self) -> i32 {
+        match self {
+            Accidental::Natural => 0,
+            Accidental::Sharp => 1,
+            Accidental::Flat => -1,
+        }
+    }
+}
+
+/// Error returned when parsing a pitch
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum ParsePitchError {
+    /// The note name is not one of `A` to `G`
+    InvalidName,
+    /// The octave is missing or not a number
+    InvalidOctave,
+    /// The pitch is outside of the MIDI range
+    OutOfRange,
+}
+
+/// A pitch in 12 tone equal temperament
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Pitch {
+    midi: u8,
+}
+
+impl Pitch {
+    /// Middle C
+    pub const C4: Pitch = Pitch { midi: 60 };
+    /// Concert A
+    pub const A4: Pitch = Pitch { midi: 69 };
+
+    /// Creates a pitch from its MIDI note number, `None` above 127
+    pub const fn from_midi(midi: u8) -> Option<Pitch> {
+        if midi > 127 {
+            return None;
+        }
+        Some(Pitch { midi })
+    }
+
+    /// Creates a pitch from a note name, accidental and octave (C4 is middle
+    /// C), `None` outside of the MIDI range
+    pub const fn new(name: NoteName, accidental: Accidental, octave: i8) -> Option<Pitch> {
+        let midi = (octave as i32 + 1) * 12 + name.semitone() + accidental.offset();
+        if midi < 0 || midi > 127 {
+            return None;
+        }
+        Some(Pitch { midi: midi as u8 })
+    }
+
+    /// Parses a pitch like `"C4"`, `"C#4"`, `"CS4"`, `"Bb3"` or `"C-1"`
+    pub const fn parse(s: &str) -> Result<Pitch, ParsePitchError> {
+        let bytes = s.as_bytes();
+        if bytes.is_empty() {
+            return Err(ParsePitchError::InvalidName);
+        }
+        let name = match bytes[0].to_ascii_uppercase() {
+            b'C' => NoteName::C,
+            b'D' => NoteName::D,
+            b'E' => NoteName::E,
+            b'F' => NoteName::F,
+            b'G' => NoteName::G,
+            b'A' => NoteName::A,
+            b'B' => NoteName::B,
+            _ => return Err(ParsePitchError::InvalidName),
+        };
+
+        let mut i = 1;
+        // `S` is accepted for sharps where `#` cannot be used, as in `NOTE_CS4`
+        let accidental = if i < bytes.len() && matches!(bytes[i], b'#' | b'S' | b's') {
+            i += 1;
+            Accidental::Sharp
+        } else if i < bytes.len() && bytes[i] == b'b' {
+            i += 1;
+            Accidental::Flat
+        } else {
+            Accidental::Natural
+        };
+
+        let negative = i < bytes.len() && bytes[i] == b'-';
+        if negative {
+            i += 1;
+        }
+        if i == bytes.len() {
+            return Err(ParsePitchError::InvalidOctave);
+        }
+        let mut octave: i32 = 0;
+        while i < bytes.len() {
+            if !bytes[i].is_ascii_digit() || octave > 9 {
+                return Err(ParsePitchError::InvalidOctave);
+            }
+            octave = octave * 10 + (bytes[i] - b'0') as i32;
+            i += 1;
+        }
+        if negative {
+            octave = -octave;
+        }
+        if octave < -1 || octave > 9 {
+            return Err(ParsePitchError::OutOfRange);
+        }
+
+        match Pitch::new(name, accidental, octave as i8) {
+            Some(pitch) => Ok(pitch),
+            None => Err(ParsePitchError::OutOfRange),
+        }
+    }
+
+    /// Returns the MIDI note number
+    pub const fn midi(self) -> u8 {
+        self.midi
+    }
+
+    /// Returns the octave, C4 is middle C
+    pub const fn octave(self) -> i8 {
+        (self.midi / 12) as i8 - 1
+    }
+
+    /// Returns the note name and accidental, spelled with sharps
+    pub const fn name(self) -> (NoteName, Accidental) {
+        SEMITONE_NAMES[(self.midi % 12) as usize]
+    }
+
+    /// Returns the pitch `semitones` higher (or lower if negative), `None`
+    /// outside of the MIDI range
+    pub const fn transpose(self, semitones: i32) -> Option<Pitch> {
+        let midi = self.midi as i32 + semitones;
+        if midi < 0 || midi > 127 {
+            return None;
+        }
+        Some(Pitch { midi: midi as u8 })
+    }
+
+    /// Returns the frequency in Hz
+    pub const fn frequency(self, tuning: Tuning) -> f64 {
+        tuning.a4 * semitone_ratio(self.midi as i32 - A4_MIDI)
+    }
+
+    /// Returns the pitch closest to `frequency` Hz, `None` for a rest or
+    /// more than half a semitone outside of the MIDI range
+    pub fn nearest(frequency: f64, tuning: Tuning) -> Option<Pitch> {
+        if !frequency.is_finite() || frequency <= 0.0 {
+            return None;
+        }
+        // First pitch at or above the frequency
+        let (mut low, mut high) = (0u8, 128u8);
+        while low < high {
+            let mid = (low + high) / 2;
+            if (Pitch { midi: mid }).frequency(tuning) < frequency {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        // Distances are ratios, always above 1
+        let above = Pitch::from_midi(low).map(|pitch| (pitch, pitch.frequency(tuning) / frequency));
+        let below = low
+            .checked_sub(1)
+            .map(|midi| Pitch { midi })
+            .map(|pitch| (pitch, frequency / pitch.frequency(tuning)));
+        let (pitch, distance) = match (below, above) {
+            (Some(below), Some(above)) if below.1 < above.1 => below,
+            (_, Some(above)) => above,
+            (below, None) => below?,
+        };
+        (distance < HALF_SEMITONE).then_some(pitch)
+    }
+}
+
+/// Returns the frequency ratio of an interval of `semitones`, 2^(n/12)
+pub const fn semitone_ratio(semitones: i32) -> f64 {
+    let mut ratio = SEMITONE_RATIOS[semitones.rem_euclid(12) as usize];
+    let mut octaves = semitones.div_euclid(12);
+    while octaves > 0 {
+        ratio *= 2.0;
+        octaves -= 1;
+    }
+    while octaves < 0 {
+        ratio /= 2.0;
+        octaves += 1;
+    }
+    ratio
+}
+
+impl FromStr for Pitch {
+    type Err = ParsePitchError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Pitch::parse(s)
+    }
+}
+
+impl fmt::Display for Pitch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (name, accidental) = self.name();
+        let sharp = if accidental == Accidental::Sharp { "#" } else { "" };
+        core::write!(f, "{:?}{}{}", name, sharp, self.octave())
+    }
+}
+
+impl defmt::Format for Pitch {
+    fn format(&self, f: defmt::Formatter) {
+        let (name, accidental) = self.name();
+        let sharp = if accidental == Accidental::Sharp { "#" } else { "" };
+        defmt::write!(f, "{}{}{}", name, sharp, self.octave())
+    }
+}
+
+/// Modes of the diatonic scale, and the harmonic minor
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum Mode {
+    /// Ionian
+    Major,
+    /// Aeolian, the natural minor
+    Minor,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Locrian,
+    /// Minor with a raised seventh
+    HarmonicMinor,
+}
+
+impl Mode {
+    /// Semitones of the 7 degrees above the tonic
+    pub const fn steps(self) -> [i32; 7] {
+        match self {
+            Mode::Major => [0, 2, 4, 5, 7, 9, 11],
+            Mode::Minor => [0, 2, 3, 5, 7, 8, 10],
+            Mode::Dorian => [0, 2, 3, 5, 7, 9, 10],
+            Mode::Phrygian => [0, 1, 3, 5, 7, 8, 10],
+            Mode::Lydian => [0, 2, 4, 6, 7, 9, 11],
+            Mode::Mixolydian => [0, 2, 4, 5, 7, 9, 10],
+            Mode::Locrian => [0, 1, 3, 5, 6, 8, 10],
+            Mode::HarmonicMinor => [0, 2, 3, 5, 7, 8, 11],
+        }
+    }
+}
+
+/// A key, the tonic and mode of a scale
+///
+/// Pitches are numbered by scale degree, 7 per octave with degree 0 on the
+/// tonic of octave -1. Pitches outside of the scale are a degree and the
+/// semitones they are above it, so C# in C major is C raised by one.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub struct Key {
+    /// Tonic
+    pub tonic: NoteName,
+    /// Accidental of the tonic
+    pub accidental: Accidental,
+    /// Mode
+    pub mode: Mode,
+}
+
+impl Key {
+    /// C major, no sharps or flats
+    pub const C_MAJOR: Key = Key::new(NoteName::C, Accidental::Natural, Mode::Major);
+    /// A minor, no sharps or flats
+    pub const A_MINOR: Key = Key::new(NoteName::A, Accidental::Natural, Mode::Minor);
+
+    /// Creates a key from its tonic and mode
+    pub const fn new(tonic: NoteName, accidental: Accidental, mode: Mode) -> Self {
+        Self {
+            tonic,
+            accidental,
+            mode,
+        }
+    }
+
+    /// Returns the scale degree of `pitch` and the semitones it is above
+    /// that degree, 0 for the notes of the scale
+    pub const fn degree(self, pitch: Pitch) -> (i32, i32) {
+        let tonic = self.tonic.semitone() + self.accidental.offset();
+        let relative = pitch.midi as i32 - tonic;
+        let (octave, semitone) = (relative.div_euclid(12), relative.rem_euclid(12));
+        let steps = self.mode.steps();
+        let mut index = 6;
+        while steps[index] > semitone {
+            index -= 1;
+        }
+        (octave * 7 + index as i32, semitone - steps[index])
+    }
+
+    /// Returns the pitch of scale `degree` raised by `offset` semitones,
+    /// `None` outside of the MIDI range
+    pub const fn pitch(self, degree: i32, offset: i32) -> Option<Pitch> {
+        let tonic = self.tonic.semitone() + self.accidental.offset();
+        let steps = self.mode.steps();
+        let midi =
+            tonic + degree.div_euclid(7) * 12 + steps[degree.rem_euclid(7) as usize] + offset;
+        if midi < 0 || midi > 127 {
+            return None;
+        }
+        Some(Pitch { midi: midi as u8 })
+    }
+
+    /// Returns `pitch` moved by `steps` degrees of the scale, up if positive
+    /// and down if negative. A note outside of the scale keeps its offset
+    /// from the degree below.
+    pub const fn transpose(self, pitch: Pitch, steps: i32) -> Option<Pitch> {
+        let (degree, offset) = self.degree(pitch);
+        self.pitch(degree + steps, offset)
+    }
+
+    /// Mirrors `pitch` around the degree of `axis`, a third up from the axis
+    /// becomes a third down in the scale. A note outside of the scale is
+    /// lowered as much as it was raised.
+    pub const fn invert(self, pitch: Pitch, axis: Pitch) -> Option<Pitch> {
+        let (degree, offset) = self.degree(pitch);
+        let (axis, _) = self.degree(axis);
+        self.pitch(2 * axis - degree, -offset)
+    }
+}
+
+/// Returns the frequency of `name` with the standard tuning, fails to
+/// compile when used in a constant with an invalid name
+pub const fn frequency(name: &str) -> f64 {
+    match Pitch::parse(name) {
+        Ok(pitch) => pitch.frequency(Tuning::STANDARD),
+        Err(_) => panic!("invalid pitch"),
+    }
+}
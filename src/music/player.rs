@@ -0,0 +1,491 @@
+//! Async melody player for a buzzer driven by a PWM channel
+//!
+//! The player owns the [`Buzzer`] and plays `(frequency, divider)`
+//! melodies like the ones in `sing.rs`. Each note sounds for the part of its
+//! duration given by its [`Articulation`], following the [`Envelope`] of the
+//! player, and the rest is silence. Playback is controlled from other tasks
+//! by signaling a [`Command`].
+
+use defmt::warn;
+use embassy_futures::select::{Either, select};
+use embassy_rp::pwm::Pwm;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer};
+
+use super::buzzer::Buzzer;
+use super::effects::{Effect, Segment};
+use super::envelope::{self, Articulation, Envelope};
+use super::midi::{Smf, Voice};
+use super::mml::Mml;
+use super::pitch::{Pitch, Tuning};
+use super::rhythm::Event;
+use super::rtttl::Rtttl;
+use super::tone::ToneError;
+use super::{REST, Song};
+
+/// Time between two frequency changes of a sweep
+const SWEEP_STEP: Duration = Duration::from_millis(10);
+/// Level of unaccented notes at 100% volume, in per mille, which leaves
+/// room for the accent gain
+const LOUDEST: u16 =
+    (envelope::FULL as u32 * envelope::FULL as u32 / Articulation::Accent.gain() as u32) as u16;
+
+/// Playback commands
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum Command {
+    /// Stops the melody that is playing
+    Stop,
+    /// Pauses the melody, keeping its position
+    Pause,
+    /// Resumes a paused melody, or restarts a stopped [`player_task`]
+    Resume,
+}
+
+/// Signal used to send commands to a [`Player`]
+pub type PlayerControl = Signal<CriticalSectionRawMutex, Command>;
+
+/// A note or rest started by a [`Player`]
+#[derive(Debug, Copy, Clone, PartialEq, defmt::Format)]
+pub struct NoteEvent {
+    /// Frequency in Hz, [`REST`] for a rest
+    pub frequency: f64,
+    /// Nearest pitch with the standard tuning, `None` for a rest
+    pub pitch: Option<Pitch>,
+    /// Instant the note is scheduled to start
+    pub start: Instant,
+    /// Length of the note, until the next one starts
+    pub length: Duration,
+}
+
+/// Channel on which a [`Player`] publishes the notes it starts
+pub type NoteChannel = Channel<CriticalSectionRawMutex, NoteEvent, 4>;
+
+/// Playback was stopped with [`Command::Stop`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub struct Stopped;
+
+/// Melody player
+pub struct Player<'d> {
+    buzzer: Buzzer<'d>,
+    control: &'d PlayerControl,
+    envelope: Envelope,
+    volume: u8,
+    notes: Option<&'d NoteChannel>,
+}
+
+impl<'d> Player<'d> {
+    /// Creates a player for the buzzer on the B output of `pwm`
+    pub fn new(pwm: Pwm<'d>, control: &'d PlayerControl) -> Self {
+        Self {
+            buzzer: Buzzer::new(pwm),
+            control,
+            envelope: Envelope::ORGAN,
+            volume: 100,
+            notes: None,
+        }
+    }
+
+    /// Returns the envelope applied to the notes
+    pub fn envelope(&self) -> Envelope {
+        self.envelope
+    }
+
+    /// Changes the envelope applied to the notes
+    pub fn set_envelope(&mut self, envelope: Envelope) {
+        self.envelope = envelope;
+    }
+
+    /// Returns the volume, in percent
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    /// Changes the volume, in percent. At the default of 100%, notes play
+    /// at 80% of a 50% duty cycle, so accented notes, 25% louder, reach the
+    /// loudest a buzzer gets.
+    pub fn set_volume(&mut self, volume: u8) {
+        self.volume = volume.min(100);
+    }
+
+    /// Publishes the notes and rests played from now on to `notes`, for
+    /// visualizers. Notes are dropped while the channel is full, so a slow
+    /// receiver does not hold up playback.
+    pub fn set_note_channel(&mut self, notes: &'d NoteChannel) {
+        self.notes = Some(notes);
+    }
+
+    /// Plays `melody` once at the tempo of `song`
+    pub async fn play(&mut self, melody: &[(f64, i16)], song: &Song) -> Result<(), Stopped> {
+        self.play_iter(melody.iter().copied(), song).await
+    }
+
+    /// Plays the `(frequency, divider)` notes of `melody` once at the tempo
+    /// of `song`
+    pub async fn play_iter<I>(&mut self, melody: I, song: &Song) -> Result<(), Stopped>
+    where
+        I: IntoIterator<Item = (f64, i16)>,
+    {
+        let result = self.play_notes(melody, song).await;
+        self.silence();
+        result
+    }
+
+    /// Plays a ring tone once
+    pub async fn play_rtttl(&mut self, rtttl: &Rtttl<'_>) -> Result<(), Stopped> {
+        self.play_iter(rtttl.melody(Tuning::STANDARD), &rtttl.song())
+            .await
+    }
+
+    /// Plays an MML melody once
+    pub async fn play_mml(&mut self, mml: &Mml<'_>) -> Result<(), Stopped> {
+        self.play_events(mml.events(Tuning::STANDARD), &mml.song())
+            .await
+    }
+
+    /// Plays a sound effect once
+    pub async fn play_effect(&mut self, effect: &Effect) -> Result<(), Stopped> {
+        let result = self.play_segments(effect, true).await;
+        self.silence();
+        result
+    }
+
+    /// Plays a sound effect once without following the commands, which are
+    /// left for the next melody
+    pub async fn play_effect_ignoring_commands(&mut self, effect: &Effect) {
+        let _ = self.play_segments(effect, false).await;
+        self.silence();
+    }
+
+    async fn play_segments(&mut self, effect: &Effect, controlled: bool) -> Result<(), Stopped> {
+        let mut deadline = Instant::now();
+        for _ in 0..effect.repeat {
+            for segment in effect.segments {
+                match *segment {
+                    Segment::Tone {
+                        frequency,
+                        duration,
+                    } => {
+                        self.start_note(frequency);
+                        deadline += duration;
+                        deadline += self.wait_for(deadline, controlled).await?;
+                    }
+                    Segment::Sweep { from, to, duration } => {
+                        let steps = (duration.as_ticks() / SWEEP_STEP.as_ticks()).max(1) as u32;
+                        let step = duration / steps;
+                        for i in 0..steps {
+                            self.start_note(from + (to - from) * i as f64 / steps as f64);
+                            deadline += step;
+                            deadline += self.wait_for(deadline, controlled).await?;
+                        }
+                        // Rounding leftover, waited for by the next segment
+                        deadline += duration - step * steps;
+                    }
+                    Segment::Silence(duration) => {
+                        self.silence();
+                        deadline += duration;
+                        deadline += self.wait_for(deadline, controlled).await?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Plays `(frequency, duration)` notes back to back, without the gap
+    /// between notes, as needed for notes that carry their own timing
+    pub async fn play_timed<I>(&mut self, melody: I) -> Result<(), Stopped>
+    where
+        I: IntoIterator<Item = (f64, Duration)>,
+    {
+        let result = self.play_timed_notes(melody).await;
+        self.silence();
+        result
+    }
+
+    /// Plays the monophonic line of `voice` from a MIDI file once
+    pub async fn play_midi(&mut self, smf: &Smf<'_>, voice: Voice) -> Result<(), Stopped> {
+        self.play_timed(smf.melody(voice, Tuning::STANDARD)).await
+    }
+
+    async fn play_timed_notes<I>(&mut self, melody: I) -> Result<(), Stopped>
+    where
+        I: IntoIterator<Item = (f64, Duration)>,
+    {
+        let mut deadline = Instant::now();
+        for (note, duration) in melody {
+            self.start_note(note);
+            deadline += duration;
+            deadline += self.wait_until(deadline).await?;
+        }
+        Ok(())
+    }
+
+    async fn play_notes<I>(&mut self, melody: I, song: &Song) -> Result<(), Stopped>
+    where
+        I: IntoIterator<Item = (f64, i16)>,
+    {
+        let mut deadline = Instant::now();
+        for (note, divider) in melody {
+            let length = song.divider_duration(divider);
+            self.play_note(&mut deadline, note, length, Articulation::Normal, &mut None)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Plays song events once, starting at the tempo and time signature of
+    /// `song`
+    ///
+    /// Notes are scheduled at absolute instants computed from the start of
+    /// the song, so the time spent between notes does not add up.
+    pub async fn play_events<I>(&mut self, events: I, song: &Song) -> Result<(), Stopped>
+    where
+        I: IntoIterator<Item = Event>,
+    {
+        let result = self.play_event_list(events, song).await;
+        self.silence();
+        result
+    }
+
+    async fn play_event_list<I>(&mut self, events: I, song: &Song) -> Result<(), Stopped>
+    where
+        I: IntoIterator<Item = Event>,
+    {
+        let mut song = *song;
+        let mut deadline = Instant::now();
+        // Start of the envelope of the previous note, if it is tied or
+        // slurred to the next one
+        let mut connected = None;
+        for event in events {
+            match event {
+                Event::Tempo(tempo) => song.set_tempo(tempo),
+                Event::TimeSignature(time_signature) => song.set_time_signature(time_signature),
+                Event::Rest(duration) => {
+                    let length = song.duration(duration);
+                    self.play_note(
+                        &mut deadline,
+                        REST,
+                        length,
+                        Articulation::Normal,
+                        &mut connected,
+                    )
+                    .await?;
+                }
+                Event::Note {
+                    frequency,
+                    duration,
+                    tie,
+                    articulation,
+                } => {
+                    // A tie holds the note like a slur to the same pitch
+                    let articulation = if tie {
+                        Articulation::Slur
+                    } else {
+                        articulation
+                    };
+                    let length = song.duration(duration);
+                    self.play_note(
+                        &mut deadline,
+                        frequency,
+                        length,
+                        articulation,
+                        &mut connected,
+                    )
+                    .await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Plays a note starting at `deadline` and advances `deadline` to its end
+    ///
+    /// The duty cycle follows the envelope while the note sounds, then
+    /// releases during the silence that ends the note. `connected` holds the
+    /// start of the envelope of a slurred note, which the next note
+    /// continues without a new attack.
+    async fn play_note(
+        &mut self,
+        deadline: &mut Instant,
+        frequency: f64,
+        length: Duration,
+        articulation: Articulation,
+        connected: &mut Option<Instant>,
+    ) -> Result<(), Stopped> {
+        if let Some(notes) = self.notes {
+            let _ = notes.try_send(NoteEvent {
+                frequency,
+                pitch: Pitch::nearest(frequency, Tuning::STANDARD),
+                start: *deadline,
+                length,
+            });
+        }
+
+        if frequency == REST {
+            *connected = None;
+            self.silence();
+            *deadline += length;
+            *deadline += self.wait_until(*deadline).await?;
+            return Ok(());
+        }
+
+        let mut peak = self.peak(articulation);
+        if let Err(err) = self.buzzer.set_frequency(frequency) {
+            warn!("Cannot play {} Hz: {}", frequency, err);
+            peak = 0;
+        }
+
+        let mut start = connected.take().unwrap_or(*deadline);
+        let mut off = *deadline + articulation.sound(length);
+        let mut end = *deadline + length;
+        let mut now = *deadline;
+        let mut level;
+        loop {
+            let elapsed = now.saturating_duration_since(start);
+            level = scale(peak, self.envelope.level(elapsed));
+            self.buzzer.set_level(level);
+            if now >= off {
+                break;
+            }
+            let next = if elapsed < self.envelope.settled() {
+                (now + envelope::STEP).min(off)
+            } else {
+                off
+            };
+            let paused = self.wait_until(next).await?;
+            (now, start, off, end) = (next + paused, start + paused, off + paused, end + paused);
+        }
+
+        if articulation == Articulation::Slur {
+            *connected = Some(start);
+            *deadline = end;
+            return Ok(());
+        }
+
+        let mut release_end = (off + self.envelope.release).min(end);
+        while now < release_end {
+            self.buzzer.set_level(self.envelope.release_level(level, now - off));
+            let next = (now + envelope::STEP).min(release_end);
+            let paused = self.wait_until(next).await?;
+            (now, off, release_end, end) = (
+                next + paused,
+                off + paused,
+                release_end + paused,
+                end + paused,
+            );
+        }
+        self.silence();
+        *deadline = end;
+        *deadline += self.wait_until(end).await?;
+        Ok(())
+    }
+
+    /// Returns the peak level of a note, in per mille of a 50% duty cycle
+    fn peak(&self, articulation: Articulation) -> u16 {
+        let level = scale(self.volume as u16 * 10, LOUDEST);
+        scale(level, articulation.gain())
+    }
+
+    /// Starts `frequency`, or silence for a rest
+    fn start_note(&mut self, frequency: f64) {
+        if frequency == REST {
+            self.silence();
+        } else if let Err(err) = self.tone(frequency) {
+            warn!("Cannot play {} Hz: {}", frequency, err);
+            self.silence();
+        }
+    }
+
+    /// Starts a square wave of `frequency` Hz on the buzzer, at the volume
+    /// of the player
+    pub fn tone(&mut self, frequency: f64) -> Result<(), ToneError> {
+        self.buzzer.set_frequency(frequency)?;
+        self.buzzer.set_level(self.peak(Articulation::Normal));
+        Ok(())
+    }
+
+    /// Silences the buzzer
+    pub fn silence(&mut self) {
+        self.buzzer.silence();
+    }
+
+    /// Waits for `duration` of playback time, which does not advance while
+    /// the player is paused
+    pub async fn wait(&mut self, duration: Duration) -> Result<(), Stopped> {
+        self.wait_until(Instant::now() + duration).await.map(|_| ())
+    }
+
+    /// Waits for [`Command::Resume`], after playback was stopped
+    pub async fn wait_resume(&mut self) {
+        while self.control.wait().await != Command::Resume {}
+    }
+
+    /// Waits until `deadline` like [`Player::wait_until`] if `controlled`,
+    /// otherwise without reading the commands
+    async fn wait_for(&mut self, deadline: Instant, controlled: bool) -> Result<Duration, Stopped> {
+        if controlled {
+            return self.wait_until(deadline).await;
+        }
+        Timer::at(deadline).await;
+        Ok(Duration::from_ticks(0))
+    }
+
+    /// Waits until `deadline`, pushed back by the time spent paused. Returns
+    /// the time spent paused, by which later deadlines have to be pushed back.
+    pub async fn wait_until(&mut self, deadline: Instant) -> Result<Duration, Stopped> {
+        let buzzer = &mut self.buzzer;
+        wait_until(self.control, deadline, |mute| buzzer.mute(mute)).await
+    }
+}
+
+/// Scales `level` by `factor` per mille
+pub(crate) fn scale(level: u16, factor: u16) -> u16 {
+    (level as u32 * factor as u32 / 1000) as u16
+}
+
+/// Waits until `deadline`, pushed back by the time spent paused by
+/// `control`. `mute` is called with `true` when playback pauses and `false`
+/// when it resumes. Returns the time spent paused.
+pub(crate) async fn wait_until(
+    control: &PlayerControl,
+    deadline: Instant,
+    mut mute: impl FnMut(bool),
+) -> Result<Duration, Stopped> {
+    let mut deadline = deadline;
+    let mut paused_for = Duration::from_ticks(0);
+    loop {
+        match select(Timer::at(deadline), control.wait()).await {
+            Either::First(_) => return Ok(paused_for),
+            Either::Second(Command::Stop) => return Err(Stopped),
+            Either::Second(Command::Resume) => {}
+            Either::Second(Command::Pause) => {
+                let paused = Instant::now();
+                mute(true);
+                loop {
+                    match control.wait().await {
+                        Command::Resume => break,
+                        Command::Stop => return Err(Stopped),
+                        Command::Pause => {}
+                    }
+                }
+                let pause = paused.elapsed();
+                deadline += pause;
+                paused_for += pause;
+                mute(false);
+            }
+        }
+    }
+}
+
+/// Plays `melody` in a loop. After a [`Command::Stop`], the task waits for
+/// [`Command::Resume`] and starts the melody over.
+#[embassy_executor::task]
+pub async fn player_task(mut player: Player<'static>, melody: &'static [(f64, i16)], song: Song) {
+    loop {
+        if player.play(melody, &song).await.is_err() {
+            player.wait_resume().await;
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! Note durations, time signatures and song events
+//!
+//! Note lengths are exact fractions of a whole note, so dotted, double-dotted
+//! and tuplet notes keep their precise length. A song is a sequence of
+//! [`Event`]s, which can also change the tempo or the time signature in the
+//! middle of the song.
+
+use super::envelope::Articulation;
+
+/// Basic note values
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum NoteValue {
+    /// Whole note
+    Whole,
+    /// Half note
+    Half,
+    /// Quarter note
+    Quarter,
+    /// Eighth note
+    Eighth,
+    /// Sixteenth note
+    Sixteenth,
+    /// Thirty-second note
+    ThirtySecond,
+    /// Sixty-fourth note
+    SixtyFourth,
+}
+
+impl NoteValue {
+    /// Returns how many of these notes make a whole note
+    pub const fn divider(self) -> u32 {
+        match self {
+            NoteValue::Whole => 1,
+            NoteValue::Half => 2,
+            NoteValue::Quarter => 4,
+            NoteValue::Eighth => 8,
+            NoteValue::Sixteenth => 16,
+            NoteValue::ThirtySecond => 32,
+            NoteValue::SixtyFourth => 64,
+        }
+    }
+
+    /// Returns the note value of a divider, `None` if it is not a power of
+    /// two up to 64
+    pub const fn from_divider(divider: u32) -> Option<NoteValue> {
+        Some(match divider {
+            1 => NoteValue::Whole,
+            2 => NoteValue::Half,
+            4 => NoteValue::Quarter,
+            8 => NoteValue::Eighth,
+            16 => NoteValue::Sixteenth,
+            32 => NoteValue::ThirtySecond,
+            64 => NoteValue::SixtyFourth,
+            _ => return None,
+        })
+    }
+}
+
+/// Length of a note or rest
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum NoteLength {
+    /// A plain note value
+    Plain(NoteValue),
+    /// One and a half times the note value
+    Dotted(NoteValue),
+    /// One and three quarter times the note value
+    DoubleDotted(NoteValue),
+    /// `actual` notes played in the time of `normal` ones, a triplet is
+    /// 3 in the time of 2
+    Tuplet {
+        /// The note value
+        value: NoteValue,
+        /// Number of notes of the group
+        actual: u8,
+        /// Number of notes the group replaces
+        normal: u8,
+    },
+}
+
+impl NoteLength {
+    /// Returns a triplet note
+    pub const fn triplet(value: NoteValue) -> NoteLength {
+        NoteLength::Tuplet {
+            value,
+            actual: 3,
+            normal: 2,
+        }
+    }
+
+    /// Returns the duration of a divider as used by
+    /// [`Song::calc_note_duration`](super::Song::calc_note_duration),
+    /// where negative dividers are dotted
+    pub const fn from_divider(divider: i16) -> Option<NoteLength> {
+        match NoteValue::from_divider(divider.unsigned_abs() as u32) {
+            Some(value) if divider > 0 => Some(NoteLength::Plain(value)),
+            Some(value) => Some(NoteLength::Dotted(value)),
+            None => None,
+        }
+    }
+
+    /// Returns the duration as a fraction of a whole note, `(numerator,
+    /// denominator)`
+    pub const fn fraction(self) -> (u32, u32) {
+        match self {
+            NoteLength::Plain(value) => (1, value.divider()),
+            NoteLength::Dotted(value) => (3, 2 * value.divider()),
+            NoteLength::DoubleDotted(value) => (7, 4 * value.divider()),
+            NoteLength::Tuplet {
+                value,
+                actual,
+                normal,
+            } => (normal as u32, actual as u32 * value.divider()),
+        }
+    }
+}
+
+/// Time signature
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub struct TimeSignature {
+    /// Beats per bar
+    pub beats: u8,
+    /// Note value of a beat
+    pub unit: NoteValue,
+}
+
+impl TimeSignature {
+    /// 4/4
+    pub const COMMON: TimeSignature = TimeSignature {
+        beats: 4,
+        unit: NoteValue::Quarter,
+    };
+
+    /// Returns the length of a bar as a fraction of a whole note
+    pub const fn bar_fraction(self) -> (u32, u32) {
+        (self.beats as u32, self.unit.divider())
+    }
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        Self::COMMON
+    }
+}
+
+/// Song event
+#[derive(Debug, Copy, Clone, PartialEq, defmt::Format)]
+pub enum Event {
+    /// A note of `frequency` Hz
+    Note {
+        /// Frequency in Hz
+        frequency: f64,
+        /// Length of the note
+        duration: NoteLength,
+        /// The note is tied to the next one, which continues it without
+        /// being played again
+        tie: bool,
+        /// How the note is played
+        articulation: Articulation,
+    },
+    /// Silence
+    Rest(NoteLength),
+    /// Changes the tempo, in quarter notes per minute
+    Tempo(u16),
+    /// Changes the time signature
+    TimeSignature(TimeSignature),
+}
+
+impl Event {
+    /// Returns a note that is not tied
+    pub const fn note(frequency: f64, duration: NoteLength) -> Event {
+        Event::articulated(frequency, duration, Articulation::Normal)
+    }
+
+    /// Returns a note that is not tied, played with `articulation`
+    pub const fn articulated(
+        frequency: f64,
+        duration: NoteLength,
+        articulation: Articulation,
+    ) -> Event {
+        Event::Note {
+            frequency,
+            duration,
+            tie: false,
+            articulation,
+        }
+    }
+}
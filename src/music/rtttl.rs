@@ -0,0 +1,315 @@
+//! RTTTL (Nokia ring tone) parser
+//!
+//! A ring tone has three sections separated by `:`, the name, the defaults
+//! and the notes, for example `Beep:d=4,o=5,b=120:8c,8p,c6,2.a#`. Each note
+//! is `[duration]letter[#][.][octave][.]`, where the letter `p` is a pause.
+//! Missing defaults are `d=4`, `o=6` and `b=63`.
+//!
+//! The whole string is validated by [`Rtttl::parse`], so iterating over the
+//! notes afterwards cannot fail and needs no allocation.
+
+use super::pitch::{Accidental, NoteName, Pitch, Tuning};
+use super::{Song, REST};
+
+/// Default note duration, when the `d` setting is missing
+const DEFAULT_DURATION: u8 = 4;
+/// Default octave, when the `o` setting is missing
+const DEFAULT_OCTAVE: u8 = 6;
+/// Default tempo, when the `b` setting is missing
+const DEFAULT_BPM: u16 = 63;
+
+/// Error returned when parsing a ring tone
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum RtttlError {
+    /// The string does not have the name, defaults and notes sections
+    MissingSection,
+    /// A default setting is unknown or its value is invalid
+    InvalidDefault,
+    /// The note at this index (0 is the first note) is invalid
+    InvalidNote(usize),
+}
+
+/// A note of a ring tone
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub struct RtttlNote {
+    /// The pitch, `None` for a pause
+    pub pitch: Option<Pitch>,
+    /// The note divider (4 is a quarter note), negative if dotted, as used
+    /// by [`Song::calc_note_duration`]
+    pub divider: i16,
+}
+
+impl RtttlNote {
+    /// Returns the `(frequency, divider)` pair played by the melody player
+    pub fn to_melody_note(self, tuning: Tuning) -> (f64, i16) {
+        let frequency = match self.pitch {
+            Some(pitch) => pitch.frequency(tuning),
+            None => REST,
+        };
+        (frequency, self.divider)
+    }
+}
+
+/// A parsed ring tone
+#[derive(Debug, Copy, Clone)]
+pub struct Rtttl<'a> {
+    /// Name of the ring tone
+    pub name: &'a str,
+    /// Default note duration
+    pub duration: u8,
+    /// Default octave
+    pub octave: u8,
+    /// Tempo in beats per minute
+    pub bpm: u16,
+    notes: &'a str,
+}
+
+impl<'a> Rtttl<'a> {
+    /// Parses and validates a ring tone
+    pub fn parse(s: &'a str) -> Result<Self, RtttlError> {
+        let mut sections = s.splitn(3, ':');
+        let (Some(name), Some(defaults), Some(notes)) =
+            (sections.next(), sections.next(), sections.next())
+        else {
+            return Err(RtttlError::MissingSection);
+        };
+
+        let mut rtttl = Rtttl {
+            name: name.trim(),
+            duration: DEFAULT_DURATION,
+            octave: DEFAULT_OCTAVE,
+            bpm: DEFAULT_BPM,
+            notes,
+        };
+
+        for setting in defaults.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (key, value) = setting.split_once('=').ok_or(RtttlError::InvalidDefault)?;
+            let value: u16 = value.trim().parse().map_err(|_| RtttlError::InvalidDefault)?;
+            match key.trim() {
+                "d" if is_duration(value) => rtttl.duration = value as u8,
+                "o" if value <= 9 => rtttl.octave = value as u8,
+                "b" if value > 0 => rtttl.bpm = value,
+                _ => return Err(RtttlError::InvalidDefault),
+            }
+        }
+
+        for (index, note) in rtttl.tokens().enumerate() {
+            rtttl.parse_note(note).ok_or(RtttlError::InvalidNote(index))?;
+        }
+
+        Ok(rtttl)
+    }
+
+    /// Returns the song timing of the ring tone
+    pub fn song(&self) -> Song {
+        Song::new(self.bpm)
+    }
+
+    /// Returns the notes
+    pub fn notes(&self) -> impl Iterator<Item = RtttlNote> + use<'a> {
+        let rtttl = *self;
+        self.tokens().filter_map(move |note| rtttl.parse_note(note))
+    }
+
+    /// Returns the notes as `(frequency, divider)` pairs
+    pub fn melody(&self, tuning: Tuning) -> impl Iterator<Item = (f64, i16)> + use<'a> {
+        self.notes().map(move |note| note.to_melody_note(tuning))
+    }
+
+    /// Returns the settings of the ring tone without its name and notes, to
+    /// parse notes that are read separately with [`Rtttl::parse_note`]
+    pub fn defaults(&self) -> Rtttl<'static> {
+        Rtttl {
+            name: "",
+            duration: self.duration,
+            octave: self.octave,
+            bpm: self.bpm,
+            notes: "",
+        }
+    }
+
+    fn tokens(&self) -> impl Iterator<Item = &'a str> + use<'a> {
+        let notes = self.notes;
+        notes.split(',').map(str::trim).filter(|s| !s.is_empty())
+    }
+
+    /// Parses one note of the notes section with the defaults of the ring
+    /// tone, `None` if it is invalid
+    pub fn parse_note(&self, note: &str) -> Option<RtttlNote> {
+        let bytes = note.as_bytes();
+        let mut i = 0;
+
+        let duration = match read_number(bytes, &mut i) {
+            Some(duration) if is_duration(duration) => duration,
+            Some(_) => return None,
+            None => self.duration as u16,
+        };
+
+        let name = match bytes.get(i)?.to_ascii_lowercase() {
+            b'c' => Some(NoteName::C),
+            b'd' => Some(NoteName::D),
+            b'e' => Some(NoteName::E),
+            b'f' => Some(NoteName::F),
+            b'g' => Some(NoteName::G),
+            b'a' => Some(NoteName::A),
+            b'b' | b'h' => Some(NoteName::B),
+            b'p' => None,
+            _ => return None,
+        };
+        i += 1;
+
+        let mut accidental = Accidental::Natural;
+        if bytes.get(i) == Some(&b'#') {
+            accidental = Accidental::Sharp;
+            i += 1;
+        }
+
+        // The dot is found both before and after the octave in the wild
+        let mut dotted = false;
+        if bytes.get(i) == Some(&b'.') {
+            dotted = true;
+            i += 1;
+        }
+        let octave = read_number(bytes, &mut i).unwrap_or(self.octave as u16);
+        if bytes.get(i) == Some(&b'.') {
+            dotted = true;
+            i += 1;
+        }
+        if i != bytes.len() || octave > 9 {
+            return None;
+        }
+
+        let pitch = match name {
+            Some(name) => Some(Pitch::new(name, accidental, octave as i8)?),
+            None => None,
+        };
+        let divider = if dotted { -(duration as i16) } else { duration as i16 };
+        Some(RtttlNote { pitch, divider })
+    }
+}
+
+/// Returns whether `value` is a valid note duration
+fn is_duration(value: u16) -> bool {
+    matches!(value, 1 | 2 | 4 | 8 | 16 | 32 | 64)
+}
+
+/// Reads a decimal number starting at `i`, advancing `i` past it
+fn read_number(bytes: &[u8], i: &mut usize) -> Option<u16> {
+    let start = *i;
+    let mut value: u16 = 0;
+    while let Some(digit) = bytes.get(*i).filter(|b| b.is_ascii_digit()) {
+        value = value.saturating_mul(10).saturating_add((digit - b'0') as u16);
+        *i += 1;
+    }
+    (*i > start).then_some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOKIA: &str = "Nokia:d=4,o=5,b=225:8e6,8d6,f#,g#,8c#6,8b,d,e,8b,8a,c#,e,2a";
+
+    const MISSION_IMPOSSIBLE: &str = "MissionImp:d=16,o=6,b=95:32d,32d#,32d,32d#,32d,32d#,\
+        32d,32d#,32d,32d,32d#,32e,32f,32f#,32g,g,8p,g,8p,a#,p,c7,p,g,8p,g,8p,f,p,f#,p,g,8p,g,\
+        8p,a#,p,c7,p,g,8p,g,8p,f,p,f#,p,a#,g,2d,32p,a#,g,2c#,32p,a#,g,2c,a#5,8c,2p,32p,a#5,g5,\
+        2f#,32p,a#5,g5,2f,32p,a#5,g5,2e,d#,8d";
+
+    const SIMPSONS: &str = "The Simpsons:d=4,o=5,b=160:c.6,e6,f#6,8a6,g.6,e6,c6,8a,8f#,8f#,\
+        8f#,2g,8p,8p,8f#,8f#,8f#,8g,a#.,8c6,8c6,8c6,c6";
+
+    fn note(pitch: &str, divider: i16) -> RtttlNote {
+        RtttlNote {
+            pitch: Some(Pitch::parse(pitch).unwrap()),
+            divider,
+        }
+    }
+
+    #[test]
+    fn nokia_tune() {
+        let rtttl = Rtttl::parse(NOKIA).unwrap();
+        assert_eq!(rtttl.name, "Nokia");
+        assert_eq!((rtttl.duration, rtttl.octave, rtttl.bpm), (4, 5, 225));
+        assert_eq!(rtttl.notes().count(), 13);
+
+        let notes: heapless::Vec<RtttlNote, 13> = rtttl.notes().collect();
+        assert_eq!(notes[0], note("E6", 8));
+        // Sharps at the default duration and octave
+        assert_eq!(notes[2], note("F#5", 4));
+        assert_eq!(notes[4], note("C#6", 8));
+        assert_eq!(notes[12], note("A5", 2));
+    }
+
+    #[test]
+    fn mission_impossible() {
+        let rtttl = Rtttl::parse(MISSION_IMPOSSIBLE).unwrap();
+        assert_eq!((rtttl.duration, rtttl.octave, rtttl.bpm), (16, 6, 95));
+        assert_eq!(rtttl.notes().count(), 75);
+        assert_eq!(rtttl.notes().filter(|note| note.pitch.is_none()).count(), 22);
+
+        let mut notes = rtttl.notes();
+        assert_eq!(notes.nth(1), Some(note("D#6", 32)));
+        // A pause at the default duration, then an explicit octave
+        assert_eq!(
+            notes.nth(18),
+            Some(RtttlNote {
+                pitch: None,
+                divider: 16,
+            })
+        );
+        assert_eq!(notes.next(), Some(note("C7", 16)));
+        assert_eq!(rtttl.notes().last(), Some(note("D6", 8)));
+    }
+
+    #[test]
+    fn simpsons() {
+        let rtttl = Rtttl::parse(SIMPSONS).unwrap();
+        assert_eq!(rtttl.name, "The Simpsons");
+        assert_eq!(rtttl.notes().count(), 23);
+
+        let notes: heapless::Vec<RtttlNote, 23> = rtttl.notes().collect();
+        // Dots before the octave, and after a sharp
+        assert_eq!(notes[0], note("C6", -4));
+        assert_eq!(notes[4], note("G6", -4));
+        assert_eq!(notes[18], note("A#5", -4));
+        assert_eq!(notes[12].pitch, None);
+
+        let melody: heapless::Vec<(f64, i16), 23> = rtttl.melody(Tuning::STANDARD).collect();
+        assert_eq!(melody[12], (REST, 8));
+        assert_eq!(melody[22], (Pitch::parse("C6").unwrap().frequency(Tuning::STANDARD), 4));
+    }
+
+    #[test]
+    fn missing_defaults() {
+        let rtttl = Rtttl::parse("Beep::c,8p,d.").unwrap();
+        assert_eq!((rtttl.duration, rtttl.octave, rtttl.bpm), (4, 6, 63));
+        assert_eq!(rtttl.song().tempo(), 63);
+
+        let notes: heapless::Vec<RtttlNote, 3> = rtttl.notes().collect();
+        assert_eq!(notes[0], note("C6", 4));
+        assert_eq!(notes[1].divider, 8);
+        assert_eq!(notes[2], note("D6", -4));
+
+        // Only the missing settings take their default
+        let rtttl = Rtttl::parse("Beep:b=140:c").unwrap();
+        assert_eq!((rtttl.duration, rtttl.octave, rtttl.bpm), (4, 6, 140));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(Rtttl::parse("Nokia").unwrap_err(), RtttlError::MissingSection);
+        assert_eq!(Rtttl::parse("Nokia:d=4").unwrap_err(), RtttlError::MissingSection);
+        assert_eq!(Rtttl::parse("Nokia:d=3:c").unwrap_err(), RtttlError::InvalidDefault);
+        assert_eq!(Rtttl::parse("Nokia:x=3:c").unwrap_err(), RtttlError::InvalidDefault);
+        assert_eq!(Rtttl::parse("Nokia:b=0:c").unwrap_err(), RtttlError::InvalidDefault);
+
+        // The index counts the notes from 0
+        assert_eq!(
+            Rtttl::parse("Nokia:d=4,o=5,b=225:8e6,8d6,x,g#").unwrap_err(),
+            RtttlError::InvalidNote(2)
+        );
+        assert_eq!(Rtttl::parse("Beep::c,3c").unwrap_err(), RtttlError::InvalidNote(1));
+        assert_eq!(Rtttl::parse("Beep::c,d#.,e10").unwrap_err(), RtttlError::InvalidNote(2));
+        assert_eq!(Rtttl::parse("Beep::c6x").unwrap_err(), RtttlError::InvalidNote(0));
+    }
+}
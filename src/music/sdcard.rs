@@ -0,0 +1,517 @@
+//! Songs loaded from an SD card
+//!
+//! A [`SongLoader`] lists the songs of a directory on the first FAT volume
+//! of a card and plays them. FAT short names keep 3 letters of the
+//! extension, so the formats are recognized by:
+//!
+//! * `.RTT`, ring tones (RTTTL), see [`rtttl`](super::rtttl)
+//! * `.MEL`, melodies written like the [`melody!`](crate::melody) notation,
+//!   a `tempo=120` line followed by `NAME/DURATION` notes separated by
+//!   spaces or new lines, with `;` comments until the end of the line
+//! * `.MID`, Standard MIDI Files
+//!
+//! Songs are read in small chunks while they play, so files of any length
+//! need a few bytes of RAM. The tracks of a MIDI file are played together,
+//! each is read from its own offset in the file.
+
+use core::fmt::Debug;
+
+use embedded_sdmmc::{
+    BlockDevice, Error, Mode, RawDirectory, RawFile, RawVolume, ShortFileName, TimeSource,
+    VolumeIdx, VolumeManager,
+};
+use heapless::Vec;
+
+use super::Song;
+use super::melody;
+use super::midi::{self, MAX_TRACKS, MonoNotes, SmfError, TrackSource, Voice};
+use super::pitch::Tuning;
+use super::player::Player;
+use super::rtttl::Rtttl;
+
+/// Maximum number of songs listed by [`SongLoader::scan`]
+pub const MAX_SONGS: usize = 32;
+
+/// Size of the chunks read from a file
+const CHUNK: usize = 64;
+/// Maximum length of a note
+const TOKEN_LEN: usize = 16;
+/// Maximum length of the name and defaults of a ring tone
+const HEADER_LEN: usize = 96;
+/// Size of the chunks read from each track of a MIDI file
+const TRACK_CHUNK: usize = 32;
+
+/// Format of a song file
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum SongFormat {
+    /// Ring tone, `.RTT`
+    Rtttl,
+    /// Melody notation, `.MEL`
+    Melody,
+    /// Standard MIDI File, `.MID`
+    Midi,
+}
+
+impl SongFormat {
+    /// Returns the format of a file extension
+    pub fn from_extension(extension: &[u8]) -> Option<SongFormat> {
+        let mut upper = [0; 3];
+        let upper = upper.get_mut(..extension.len())?;
+        upper.copy_from_slice(extension);
+        upper.make_ascii_uppercase();
+        match &*upper {
+            b"RTT" => Some(SongFormat::Rtttl),
+            b"MEL" => Some(SongFormat::Melody),
+            b"MID" => Some(SongFormat::Midi),
+            _ => None,
+        }
+    }
+}
+
+/// A song file found on the card
+#[derive(Debug, Clone)]
+pub struct SongFile {
+    /// Name of the file
+    pub name: ShortFileName,
+    /// Format of the file
+    pub format: SongFormat,
+    /// Size in bytes
+    pub size: u32,
+}
+
+/// Error returned when loading a song
+#[derive(Debug)]
+pub enum LoadError<E: Debug> {
+    /// The card or the file system returned an error
+    Device(Error<E>),
+    /// The file is not a valid song of its format
+    Invalid,
+    /// Playback was stopped with a [`Command`](super::player::Command)
+    Stopped,
+}
+
+impl<E: Debug> From<Error<E>> for LoadError<E> {
+    fn from(error: Error<E>) -> Self {
+        LoadError::Device(error)
+    }
+}
+
+/// Lists and plays the songs of a directory
+pub struct SongLoader<'a, D: BlockDevice, T: TimeSource> {
+    volumes: &'a mut VolumeManager<D, T>,
+    volume: RawVolume,
+    dir: RawDirectory,
+}
+
+impl<'a, D: BlockDevice, T: TimeSource> SongLoader<'a, D, T> {
+    /// Opens the directory called `dir`, or the root directory if `None`,
+    /// on the first volume of the card
+    pub fn open(
+        volumes: &'a mut VolumeManager<D, T>,
+        dir: Option<&str>,
+    ) -> Result<Self, LoadError<D::Error>> {
+        let volume = volumes.open_raw_volume(VolumeIdx(0))?;
+        let root = match volumes.open_root_dir(volume) {
+            Ok(root) => root,
+            Err(error) => {
+                let _ = volumes.close_volume(volume);
+                return Err(error.into());
+            }
+        };
+        let dir = match dir {
+            Some(name) => {
+                let dir = volumes.open_dir(root, name);
+                let _ = volumes.close_dir(root);
+                match dir {
+                    Ok(dir) => dir,
+                    Err(error) => {
+                        let _ = volumes.close_volume(volume);
+                        return Err(error.into());
+                    }
+                }
+            }
+            None => root,
+        };
+        Ok(Self {
+            volumes,
+            volume,
+            dir,
+        })
+    }
+
+    /// Returns the song files of the directory, up to [`MAX_SONGS`]
+    pub fn scan(&mut self) -> Result<Vec<SongFile, MAX_SONGS>, LoadError<D::Error>> {
+        let mut songs = Vec::new();
+        self.volumes.iterate_dir(self.dir, |entry| {
+            if entry.attributes.is_directory() {
+                return;
+            }
+            if let Some(format) = SongFormat::from_extension(entry.name.extension()) {
+                let _ = songs.push(SongFile {
+                    name: entry.name.clone(),
+                    format,
+                    size: entry.size,
+                });
+            }
+        })?;
+        Ok(songs)
+    }
+
+    /// Opens a ring tone or melody file and returns its notes, read while
+    /// iterating
+    pub fn notes(&mut self, file: &SongFile) -> Result<NoteStream<'_, D, T>, LoadError<D::Error>> {
+        if file.format == SongFormat::Midi {
+            return Err(LoadError::Invalid);
+        }
+        let handle = self
+            .volumes
+            .open_file_in_dir(self.dir, file.name.clone(), Mode::ReadOnly)?;
+        let mut stream = NoteStream {
+            volumes: self.volumes,
+            file: handle,
+            chunk: [0; CHUNK],
+            len: 0,
+            pos: 0,
+            rtttl: None,
+            song: Song::new(120),
+            error: None,
+        };
+        stream.read_header(file.format)?;
+        Ok(stream)
+    }
+
+    /// Opens a MIDI file and locates its tracks, read while playing
+    pub fn midi(&mut self, file: &SongFile) -> Result<MidiTracks<'_, D, T>, LoadError<D::Error>> {
+        if file.format != SongFormat::Midi {
+            return Err(LoadError::Invalid);
+        }
+        let handle = self
+            .volumes
+            .open_file_in_dir(self.dir, file.name.clone(), Mode::ReadOnly)?;
+        let mut tracks = MidiTracks {
+            volumes: self.volumes,
+            file: handle,
+            division: 0,
+            tracks: Vec::new(),
+            error: None,
+        };
+        tracks.read_chunks(file.size)?;
+        Ok(tracks)
+    }
+
+    /// Plays a song file once, the highest notes of a MIDI file
+    pub async fn play(
+        &mut self,
+        player: &mut Player<'_>,
+        file: &SongFile,
+    ) -> Result<(), LoadError<D::Error>> {
+        if file.format == SongFormat::Midi {
+            let mut tracks = self.midi(file)?;
+            let mut invalid = false;
+            let notes = tracks.notes(Voice::Highest).map_while(|note| {
+                invalid |= note.is_err();
+                note.ok()
+            });
+            player
+                .play_timed(notes.map(|note| note.to_timed_note(Tuning::STANDARD)))
+                .await
+                .map_err(|_| LoadError::Stopped)?;
+            return match tracks.error.take() {
+                Some(error) => Err(error),
+                None if invalid => Err(LoadError::Invalid),
+                None => Ok(()),
+            };
+        }
+
+        let mut notes = self.notes(file)?;
+        let song = notes.song();
+        player
+            .play_iter(&mut notes, &song)
+            .await
+            .map_err(|_| LoadError::Stopped)?;
+        notes.error.take().map_or(Ok(()), Err)
+    }
+}
+
+impl<D: BlockDevice, T: TimeSource> Drop for SongLoader<'_, D, T> {
+    fn drop(&mut self) {
+        let _ = self.volumes.close_dir(self.dir);
+        let _ = self.volumes.close_volume(self.volume);
+    }
+}
+
+/// Notes of a ring tone or melody file, read from the card while iterating
+///
+/// Iteration stops at the first invalid note or read error, which is
+/// returned by [`NoteStream::error`].
+pub struct NoteStream<'a, D: BlockDevice, T: TimeSource> {
+    volumes: &'a mut VolumeManager<D, T>,
+    file: RawFile,
+    chunk: [u8; CHUNK],
+    len: usize,
+    pos: usize,
+    /// Defaults of a ring tone, `None` for a melody
+    rtttl: Option<Rtttl<'static>>,
+    song: Song,
+    error: Option<LoadError<D::Error>>,
+}
+
+impl<D: BlockDevice, T: TimeSource> NoteStream<'_, D, T> {
+    /// Returns the tempo of the song
+    pub fn song(&self) -> Song {
+        self.song
+    }
+
+    /// Returns the error that stopped the iteration, if any
+    pub fn error(&self) -> Option<&LoadError<D::Error>> {
+        self.error.as_ref()
+    }
+
+    fn read_header(&mut self, format: SongFormat) -> Result<(), LoadError<D::Error>> {
+        if format == SongFormat::Rtttl {
+            // The name and the defaults, up to the start of the notes
+            let mut header = Vec::<u8, HEADER_LEN>::new();
+            let mut colons = 0;
+            while colons < 2 {
+                let byte = self.byte().ok_or(LoadError::Invalid)?;
+                colons += (byte == b':') as u8;
+                header.push(byte).map_err(|_| LoadError::Invalid)?;
+            }
+            let header = core::str::from_utf8(&header).map_err(|_| LoadError::Invalid)?;
+            let rtttl = Rtttl::parse(header).map_err(|_| LoadError::Invalid)?;
+            self.song = rtttl.song();
+            self.rtttl = Some(rtttl.defaults());
+        } else {
+            let token = self.token().ok_or(LoadError::Invalid)?;
+            let tempo = token
+                .strip_prefix(b"tempo=")
+                .and_then(|tempo| core::str::from_utf8(tempo).ok())
+                .and_then(|tempo| tempo.parse::<u16>().ok())
+                .filter(|&tempo| tempo > 0)
+                .ok_or(LoadError::Invalid)?;
+            self.song = Song::new(tempo);
+        }
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the next byte of the file, `None` at its end or on errors
+    fn byte(&mut self) -> Option<u8> {
+        if self.pos == self.len {
+            if self.error.is_some() {
+                return None;
+            }
+            match self.volumes.read(self.file, &mut self.chunk) {
+                Ok(0) => return None,
+                Ok(len) => (self.len, self.pos) = (len, 0),
+                Err(error) => {
+                    self.error = Some(error.into());
+                    return None;
+                }
+            }
+        }
+        let byte = self.chunk[self.pos];
+        self.pos += 1;
+        Some(byte)
+    }
+
+    /// Returns the next token separated by spaces, new lines or commas,
+    /// skipping `;` comments
+    fn token(&mut self) -> Option<Vec<u8, TOKEN_LEN>> {
+        let mut token = Vec::new();
+        let mut comment = false;
+        loop {
+            let Some(byte) = self.byte() else {
+                return (!token.is_empty()).then_some(token);
+            };
+            match byte {
+                b'\n' => comment = false,
+                _ if comment => {}
+                b';' => comment = true,
+                _ if byte.is_ascii_whitespace() || byte == b',' => {}
+                _ => {
+                    if token.push(byte).is_err() {
+                        self.error = Some(LoadError::Invalid);
+                        return None;
+                    }
+                    continue;
+                }
+            }
+            if !token.is_empty() {
+                return Some(token);
+            }
+        }
+    }
+}
+
+impl<D: BlockDevice, T: TimeSource> Iterator for NoteStream<'_, D, T> {
+    type Item = (f64, i16);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = self.token()?;
+        let token = core::str::from_utf8(&token).ok();
+        let note = match self.rtttl {
+            Some(rtttl) => token
+                .and_then(|note| rtttl.parse_note(note))
+                .map(|note| note.to_melody_note(Tuning::STANDARD)),
+            None => token.and_then(melody::parse_note),
+        };
+        if note.is_none() {
+            self.error = Some(LoadError::Invalid);
+        }
+        note
+    }
+}
+
+impl<D: BlockDevice, T: TimeSource> Drop for NoteStream<'_, D, T> {
+    fn drop(&mut self) {
+        let _ = self.volumes.close_file(self.file);
+    }
+}
+
+/// Position in a track of a MIDI file
+struct TrackCursor {
+    /// Offset in the file of the start of the track
+    start: u32,
+    /// Offset in the file of the next chunk
+    offset: u32,
+    /// Offset in the file of the end of the track
+    end: u32,
+    chunk: [u8; TRACK_CHUNK],
+    len: usize,
+    pos: usize,
+}
+
+/// Tracks of a MIDI file, read from the card while playing
+///
+/// Each track is read in chunks from its own offset, so the tracks of a
+/// file of any length take a few hundred bytes of RAM. A read error ends
+/// the notes with [`SmfError::Read`] and is returned by
+/// [`MidiTracks::error`].
+pub struct MidiTracks<'a, D: BlockDevice, T: TimeSource> {
+    volumes: &'a mut VolumeManager<D, T>,
+    file: RawFile,
+    division: u16,
+    tracks: Vec<TrackCursor, MAX_TRACKS>,
+    error: Option<LoadError<D::Error>>,
+}
+
+impl<D: BlockDevice, T: TimeSource> MidiTracks<'_, D, T> {
+    /// Returns the ticks per quarter note
+    pub fn division(&self) -> u16 {
+        self.division
+    }
+
+    /// Returns the number of tracks
+    pub fn track_count(&self) -> usize {
+        self.tracks.len()
+    }
+
+    /// Returns the monophonic line of `voice`, from the start of the tracks
+    pub fn notes(&mut self, voice: Voice) -> MonoNotes<&mut Self> {
+        for track in &mut self.tracks {
+            (track.offset, track.len, track.pos) = (track.start, 0, 0);
+        }
+        let (tracks, division) = (self.tracks.len(), self.division);
+        MonoNotes::new(self, tracks, division, voice)
+    }
+
+    /// Returns the error that stopped the notes, if any
+    pub fn error(&self) -> Option<&LoadError<D::Error>> {
+        self.error.as_ref()
+    }
+
+    /// Reads the header and the offsets of the tracks
+    fn read_chunks(&mut self, size: u32) -> Result<(), LoadError<D::Error>> {
+        let mut offset = 0;
+        while offset < size {
+            let mut header = [0; 8];
+            self.volumes.file_seek_from_start(self.file, offset)?;
+            self.read_exact(&mut header)?;
+            let len = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+            let start = offset + 8;
+            let end = start
+                .checked_add(len)
+                .filter(|&end| end <= size)
+                .ok_or(LoadError::Invalid)?;
+            if offset == 0 {
+                let mut body = [0; 6];
+                if &header[..4] != b"MThd" || len < 6 {
+                    return Err(LoadError::Invalid);
+                }
+                self.read_exact(&mut body)?;
+                let (_, division) = midi::parse_header(&body).map_err(|_| LoadError::Invalid)?;
+                self.division = division;
+            } else if &header[..4] == b"MTrk" {
+                // Unknown chunks are skipped
+                let track = TrackCursor {
+                    start,
+                    offset: start,
+                    end,
+                    chunk: [0; TRACK_CHUNK],
+                    len: 0,
+                    pos: 0,
+                };
+                self.tracks.push(track).map_err(|_| LoadError::Invalid)?;
+            }
+            offset = end;
+        }
+        // The division of a valid header is never 0
+        if self.division == 0 {
+            return Err(LoadError::Invalid);
+        }
+        Ok(())
+    }
+
+    /// Fills `buffer` from the file, the end of the file is an error
+    fn read_exact(&mut self, buffer: &mut [u8]) -> Result<(), LoadError<D::Error>> {
+        let mut len = 0;
+        while len < buffer.len() {
+            match self.volumes.read(self.file, &mut buffer[len..])? {
+                0 => return Err(LoadError::Invalid),
+                read => len += read,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<D: BlockDevice, T: TimeSource> TrackSource for MidiTracks<'_, D, T> {
+    fn next_byte(&mut self, track: usize) -> Result<Option<u8>, SmfError> {
+        let Some(cursor) = self.tracks.get_mut(track) else {
+            return Ok(None);
+        };
+        if cursor.pos == cursor.len {
+            if cursor.offset == cursor.end {
+                return Ok(None);
+            }
+            let len = (cursor.end - cursor.offset).min(TRACK_CHUNK as u32) as usize;
+            let read = match self.volumes.file_seek_from_start(self.file, cursor.offset) {
+                Ok(()) => self.volumes.read(self.file, &mut cursor.chunk[..len]),
+                Err(error) => Err(error),
+            };
+            match read {
+                Ok(0) => return Err(SmfError::Truncated),
+                Ok(read) => {
+                    cursor.offset += read as u32;
+                    (cursor.len, cursor.pos) = (read, 0);
+                }
+                Err(error) => {
+                    self.error = Some(error.into());
+                    return Err(SmfError::Read);
+                }
+            }
+        }
+        let byte = cursor.chunk[cursor.pos];
+        cursor.pos += 1;
+        Ok(Some(byte))
+    }
+}
+
+impl<D: BlockDevice, T: TimeSource> Drop for MidiTracks<'_, D, T> {
+    fn drop(&mut self) {
+        let _ = self.volumes.close_file(self.file);
+    }
+}
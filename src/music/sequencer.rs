@@ -0,0 +1,197 @@
+//! Polyphonic playback on several buzzers
+//!
+//! A [`Score`] holds one `(frequency, divider)` track per voice, like a
+//! melody and its bass line, or the notes of chords split across tracks.
+//! The [`Sequencer`] plays each track on its own buzzer, driven by its own
+//! PWM slice. All note changes are scheduled from the same start instant,
+//! so the voices stay aligned however long the tracks are.
+
+use defmt::warn;
+use embassy_rp::pwm::Pwm;
+use embassy_time::{Duration, Instant};
+
+use super::buzzer::Buzzer;
+use super::envelope::{self, Articulation, Envelope};
+use super::player::{self, PlayerControl, Stopped};
+use super::{REST, Song};
+
+/// Most voices a sequencer can drive
+pub const MAX_VOICES: usize = 4;
+
+/// A song with one track per voice
+#[derive(Clone, Copy)]
+pub struct Score<'a> {
+    /// Tempo of all tracks
+    pub song: Song,
+    /// `(frequency, divider)` notes of each voice, negative dividers are
+    /// dotted
+    pub tracks: &'a [&'a [(f64, i16)]],
+}
+
+/// Position in a track of `(frequency, divider)` notes
+///
+/// Times are offsets from the start of the track. The current note starts
+/// at `start`, sounds until `off` as a [`Articulation::Normal`] note, and
+/// is silent until `end`, where the next note starts.
+#[derive(Debug, Copy, Clone)]
+pub struct NoteCursor<'a> {
+    track: &'a [(f64, i16)],
+    index: usize,
+    /// Frequency of the current note, [`REST`] before the first note
+    pub frequency: f64,
+    /// Start of the current note
+    pub start: Duration,
+    /// End of the sound of the current note
+    pub off: Duration,
+    /// End of the current note
+    pub end: Duration,
+}
+
+impl<'a> NoteCursor<'a> {
+    /// Creates a cursor before the first note of `track`
+    pub const fn new(track: &'a [(f64, i16)]) -> Self {
+        Self {
+            track,
+            index: 0,
+            frequency: REST,
+            start: Duration::from_ticks(0),
+            off: Duration::from_ticks(0),
+            end: Duration::from_ticks(0),
+        }
+    }
+
+    /// Moves to the next note at the tempo of `song`, returns `false` at
+    /// the end of the track
+    pub fn advance(&mut self, song: &Song) -> bool {
+        let Some(&(frequency, divider)) = self.track.get(self.index) else {
+            return false;
+        };
+        let length = song.divider_duration(divider);
+        self.index += 1;
+        self.frequency = frequency;
+        self.start = self.end;
+        self.off = self.start + Articulation::Normal.sound(length);
+        self.end = self.start + length;
+        true
+    }
+}
+
+/// Plays a [`Score`] on up to [`MAX_VOICES`] buzzers
+pub struct Sequencer<'d, const N: usize> {
+    buzzers: [Buzzer<'d>; N],
+    control: &'d PlayerControl,
+    envelope: Envelope,
+    volume: u8,
+}
+
+impl<'d, const N: usize> Sequencer<'d, N> {
+    /// Creates a sequencer for the buzzers on the B outputs of `pwms`, the
+    /// first one plays the first track
+    pub fn new(pwms: [Pwm<'d>; N], control: &'d PlayerControl) -> Self {
+        const { assert!(N > 0 && N <= MAX_VOICES, "1 to 4 voices") };
+        Self {
+            buzzers: pwms.map(Buzzer::new),
+            control,
+            envelope: Envelope::ORGAN,
+            volume: 100,
+        }
+    }
+
+    /// Returns the envelope applied to the notes
+    pub fn envelope(&self) -> Envelope {
+        self.envelope
+    }
+
+    /// Changes the envelope applied to the notes of all voices
+    pub fn set_envelope(&mut self, envelope: Envelope) {
+        self.envelope = envelope;
+    }
+
+    /// Returns the volume, in percent
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    /// Changes the volume of all voices, in percent
+    pub fn set_volume(&mut self, volume: u8) {
+        self.volume = volume.min(100);
+    }
+
+    /// Plays all tracks of `score` once, returning when the longest track
+    /// ends. Tracks beyond the number of buzzers are not played.
+    pub async fn play(&mut self, score: &Score<'_>) -> Result<(), Stopped> {
+        if score.tracks.len() > N {
+            warn!("Playing {} of {} tracks", N, score.tracks.len());
+        }
+        let result = self.play_tracks(score).await;
+        self.silence();
+        result
+    }
+
+    async fn play_tracks(&mut self, score: &Score<'_>) -> Result<(), Stopped> {
+        let mut start = Instant::now();
+        let mut cursors: [NoteCursor; N] = core::array::from_fn(|voice| {
+            NoteCursor::new(score.tracks.get(voice).copied().unwrap_or(&[]))
+        });
+        let peak = self.volume as u16 * 10;
+
+        loop {
+            let now = Instant::now().saturating_duration_since(start);
+            let mut next = None;
+            for (buzzer, cursor) in self.buzzers.iter_mut().zip(&mut cursors) {
+                if let Some(change) = update(buzzer, cursor, &self.envelope, peak, now, &score.song)
+                {
+                    next = Some(next.map_or(change, |next: Duration| next.min(change)));
+                }
+            }
+            let Some(next) = next else {
+                return Ok(());
+            };
+            let buzzers = &mut self.buzzers;
+            start += player::wait_until(self.control, start + next, |mute| {
+                buzzers.iter_mut().for_each(|buzzer| buzzer.mute(mute))
+            })
+            .await?;
+        }
+    }
+
+    /// Silences all buzzers
+    pub fn silence(&mut self) {
+        self.buzzers.iter_mut().for_each(Buzzer::silence);
+    }
+}
+
+/// Plays the note of `cursor` due at `now` on `buzzer`, following
+/// `envelope` up to `peak` per mille. Returns when the voice changes next,
+/// `None` once its track has ended.
+fn update(
+    buzzer: &mut Buzzer<'_>,
+    cursor: &mut NoteCursor<'_>,
+    envelope: &Envelope,
+    peak: u16,
+    now: Duration,
+    song: &Song,
+) -> Option<Duration> {
+    while cursor.end <= now {
+        if !cursor.advance(song) {
+            buzzer.silence();
+            return None;
+        }
+        if cursor.frequency == REST {
+            buzzer.silence();
+        } else if let Err(err) = buzzer.set_frequency(cursor.frequency) {
+            warn!("Cannot play {} Hz: {}", cursor.frequency, err);
+            // Played as a rest
+            cursor.frequency = REST;
+            buzzer.silence();
+        }
+    }
+    if cursor.frequency == REST {
+        return Some(cursor.end);
+    }
+    let elapsed = now - cursor.start;
+    let sound = cursor.off - cursor.start;
+    let level = envelope.note_level(elapsed, sound);
+    buzzer.set_level(player::scale(peak, level).min(envelope::FULL));
+    Some(cursor.start + envelope.next_change(elapsed, sound, cursor.end - cursor.start))
+}
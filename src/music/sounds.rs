@@ -0,0 +1,235 @@
+//! Sound effects with priorities over background music
+//!
+//! The [`sound_task`] owns the buzzer. It plays the background music, if
+//! any, and any task can ask it for a sound effect through [`Sounds`]. An
+//! effect interrupts the music, which then continues from the interrupted
+//! note, and effects of the same or a higher priority interrupt each other.
+//! Requests of a lower priority than the effect that plays are dropped.
+//!
+//! The commands of the player only control the music: a `Stop` stops it
+//! until a `Resume` starts it over, effects are still played meanwhile.
+//! Effects do not read the commands, so the ones sent while an effect plays
+//! are left for the music. The [`MusicState`] follows these rules.
+
+use core::cell::Cell;
+
+#[cfg(not(feature = "std"))]
+use embassy_futures::select::{Either, select};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+
+#[cfg(not(feature = "std"))]
+use super::Song;
+use super::effects::Effect;
+#[cfg(not(feature = "std"))]
+use super::player::{Player, Stopped};
+
+/// Priority of a sound effect, background music is below all of them
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, defmt::Format)]
+pub enum Priority {
+    /// Feedback for user actions, like clicks
+    Feedback,
+    /// Notifications, like the end of a countdown
+    Notification,
+    /// Alarms
+    Alarm,
+}
+
+/// Sound effect requests for a [`sound_task`]
+pub struct Sounds {
+    request: Signal<CriticalSectionRawMutex, &'static Effect>,
+    playing: Mutex<CriticalSectionRawMutex, Cell<Option<Priority>>>,
+}
+
+impl Sounds {
+    /// Creates an empty request queue
+    pub const fn new() -> Self {
+        Self {
+            request: Signal::new(),
+            playing: Mutex::new(Cell::new(None)),
+        }
+    }
+
+    /// Asks for `effect` to be played, returns `false` if it was dropped
+    /// because an effect with a higher priority plays
+    pub fn play(&self, effect: &'static Effect, priority: Priority) -> bool {
+        self.playing.lock(|playing| {
+            if playing.get().is_some_and(|current| current > priority) {
+                return false;
+            }
+            playing.set(Some(priority));
+            self.request.signal(effect);
+            true
+        })
+    }
+
+    /// Returns the priority of the effect that plays, `None` while only the
+    /// music plays
+    pub fn playing(&self) -> Option<Priority> {
+        self.playing.lock(Cell::get)
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn finished(&self) {
+        self.playing.lock(|playing| {
+            // A request may have come in since the last effect started
+            if !self.request.signaled() {
+                playing.set(None);
+            }
+        });
+    }
+}
+
+impl Default for Sounds {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// State of the background music of a [`sound_task`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum MusicState {
+    /// Playing, from the note at this index
+    Playing(usize),
+    /// Stopped until it is resumed
+    Stopped,
+}
+
+/// What ended the playback of the music, or the wait for it
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum MusicEvent {
+    /// An effect was requested after `started` notes had started
+    Interrupted {
+        /// Notes started since the music was last started or resumed
+        started: usize,
+    },
+    /// The last note ended
+    Ended,
+    /// The music was stopped
+    Stopped,
+    /// The music was resumed
+    Resumed,
+}
+
+impl MusicState {
+    /// Returns the state after `event`
+    pub fn next(self, event: MusicEvent) -> MusicState {
+        match (self, event) {
+            // The interrupted note is played again
+            (MusicState::Playing(position), MusicEvent::Interrupted { started }) => {
+                MusicState::Playing(position + started.saturating_sub(1))
+            }
+            (MusicState::Playing(_), MusicEvent::Ended) => MusicState::Playing(0),
+            (_, MusicEvent::Stopped) => MusicState::Stopped,
+            // Started over once resumed
+            (MusicState::Stopped, MusicEvent::Resumed) => MusicState::Playing(0),
+            (state, _) => state,
+        }
+    }
+}
+
+/// Plays `music` in a loop, if any, and the effects requested through
+/// `sounds`. Once the music is stopped, only effects are played until the
+/// music is resumed.
+#[cfg(not(feature = "std"))]
+#[embassy_executor::task]
+pub async fn sound_task(
+    mut player: Player<'static>,
+    sounds: &'static Sounds,
+    music: Option<(&'static [(f64, i16)], Song)>,
+) {
+    let mut state = MusicState::Playing(0);
+    loop {
+        let mut request = match (music, state) {
+            (Some(_), MusicState::Stopped) => {
+                match select(sounds.request.wait(), player.wait_resume()).await {
+                    Either::First(request) => request,
+                    Either::Second(()) => {
+                        state = state.next(MusicEvent::Resumed);
+                        continue;
+                    }
+                }
+            }
+            (Some((melody, song)), MusicState::Playing(position)) if !melody.is_empty() => {
+                let started = Cell::new(0);
+                let notes = melody[position..]
+                    .iter()
+                    .copied()
+                    .inspect(|_| started.set(started.get() + 1));
+                match select(sounds.request.wait(), player.play_iter(notes, &song)).await {
+                    Either::First(request) => {
+                        player.silence();
+                        let started = started.get();
+                        state = state.next(MusicEvent::Interrupted { started });
+                        request
+                    }
+                    Either::Second(result) => {
+                        let event = match result {
+                            Ok(()) => MusicEvent::Ended,
+                            Err(Stopped) => MusicEvent::Stopped,
+                        };
+                        state = state.next(event);
+                        continue;
+                    }
+                }
+            }
+            _ => sounds.request.wait().await,
+        };
+
+        // The commands sent meanwhile are left for the music
+        loop {
+            let effect = player.play_effect_ignoring_commands(request);
+            match select(sounds.request.wait(), effect).await {
+                Either::First(next) => {
+                    player.silence();
+                    request = next;
+                }
+                Either::Second(()) => break,
+            }
+        }
+        sounds.finished();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn effects_resume_the_interrupted_note() {
+        let state = MusicState::Playing(0);
+        let state = state.next(MusicEvent::Interrupted { started: 5 });
+        assert_eq!(state, MusicState::Playing(4));
+        let state = state.next(MusicEvent::Interrupted { started: 3 });
+        assert_eq!(state, MusicState::Playing(6));
+        // Interrupted before the first note started
+        assert_eq!(state.next(MusicEvent::Interrupted { started: 0 }), state);
+        assert_eq!(state.next(MusicEvent::Ended), MusicState::Playing(0));
+    }
+
+    #[test]
+    fn stop_holds_the_music_through_effects() {
+        let state = MusicState::Playing(7).next(MusicEvent::Stopped);
+        assert_eq!(state, MusicState::Stopped);
+        // Effects requested while stopped do not restart the music
+        let state = state.next(MusicEvent::Interrupted { started: 0 });
+        assert_eq!(state, MusicState::Stopped);
+        let state = state.next(MusicEvent::Resumed);
+        assert_eq!(state, MusicState::Playing(0));
+    }
+
+    #[test]
+    fn resume_only_restarts_stopped_music() {
+        let state = MusicState::Playing(3).next(MusicEvent::Resumed);
+        assert_eq!(state, MusicState::Playing(3));
+        assert_eq!(
+            MusicState::Stopped.next(MusicEvent::Ended),
+            MusicState::Stopped
+        );
+        assert_eq!(
+            MusicState::Stopped.next(MusicEvent::Stopped),
+            MusicState::Stopped
+        );
+    }
+}
@@ -0,0 +1,321 @@
+//! Wavetable synthesis played through a PWM audio DAC
+//!
+//! The PWM carrier runs at the sample rate with 8 bit resolution, and DMA
+//! copies each sample to the compare register when the counter wraps. With
+//! an RC low pass filter between the pin and a speaker, the duty cycle
+//! becomes an analog signal, so voices can have smoother waveforms than the
+//! square wave of a buzzer.
+//!
+//! A [`Synth`] renders the tracks of a [`Score`] with one [`Oscillator`] per
+//! track and mixes them into blocks of samples. A [`PwmDac`] plays the
+//! blocks, rendering the next one while DMA plays the current one.
+
+use embassy_rp::dma::{self, Channel};
+use embassy_rp::pac;
+use embassy_rp::pac::dma::vals::TreqSel;
+use embassy_rp::clocks::clk_sys_freq;
+use embassy_rp::pwm::{ChannelBPin, Config, Pwm, Slice};
+use embassy_rp::{Peripheral, PeripheralRef};
+use embassy_time::Duration;
+use fixed::FixedU16;
+use fixed::types::extra::U4;
+
+use super::sequencer::{NoteCursor, Score};
+use super::tone::ToneError;
+use super::{REST, Song};
+
+/// Default sample rate, in Hz
+pub const SAMPLE_RATE: u32 = 22_050;
+
+/// Number of samples per DMA block
+pub const BLOCK: usize = 256;
+
+/// Number of entries of the sine table
+const TABLE_LEN: usize = 256;
+
+/// One period of a sine
+static SINE: [i16; TABLE_LEN] = sine_table();
+
+/// Oscillator waveforms
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum Waveform {
+    /// Sine, read from a wavetable
+    Sine,
+    /// Triangle
+    Triangle,
+    /// Rising saw tooth
+    Saw,
+    /// Square with 50% duty cycle
+    Square,
+    /// White noise, its frequency sets how often the value changes
+    Noise,
+}
+
+/// Phase accumulator oscillator
+#[derive(Debug, Copy, Clone)]
+pub struct Oscillator {
+    waveform: Waveform,
+    phase: u32,
+    step: u32,
+    noise: u32,
+    value: i16,
+}
+
+impl Oscillator {
+    /// Creates a silent oscillator
+    pub const fn new(waveform: Waveform) -> Self {
+        Self {
+            waveform,
+            phase: 0,
+            step: 0,
+            noise: 0x1234_5678,
+            value: 0,
+        }
+    }
+
+    /// Changes the frequency, a [`REST`] stops the oscillator
+    pub fn set_frequency(&mut self, frequency: f64, sample_rate: u32) {
+        if frequency == REST || frequency.is_nan() || frequency < 0.0 {
+            self.step = 0;
+            self.phase = 0;
+        } else {
+            let step = frequency * (1u64 << 32) as f64 / sample_rate as f64;
+            // Frequencies above the Nyquist limit would alias
+            self.step = step.min(u32::MAX as f64 / 2.0) as u32;
+        }
+    }
+
+    /// Returns whether the oscillator is stopped
+    pub fn is_silent(&self) -> bool {
+        self.step == 0
+    }
+
+    /// Returns the next sample
+    pub fn next_sample(&mut self) -> i16 {
+        if self.step == 0 {
+            return 0;
+        }
+        let phase = self.phase;
+        let (next, wrapped) = phase.overflowing_add(self.step);
+        self.phase = next;
+
+        match self.waveform {
+            Waveform::Sine => SINE[(phase >> 24) as usize],
+            Waveform::Triangle => {
+                // Folds the saw tooth so that it rises on the first half
+                let saw = (phase >> 16) as u16 as i32;
+                let folded = if saw < 0x8000 { saw } else { 0xFFFF - saw };
+                (folded * 2 - 0x8000) as i16
+            }
+            Waveform::Saw => ((phase >> 16) as u16 ^ 0x8000) as i16,
+            Waveform::Square => {
+                if phase < 0x8000_0000 {
+                    i16::MAX
+                } else {
+                    -i16::MAX
+                }
+            }
+            Waveform::Noise => {
+                if wrapped || self.value == 0 {
+                    // xorshift32
+                    self.noise ^= self.noise << 13;
+                    self.noise ^= self.noise >> 17;
+                    self.noise ^= self.noise << 5;
+                    self.value = (self.noise >> 16) as i16;
+                }
+                self.value
+            }
+        }
+    }
+}
+
+/// One track of a [`Synth`]
+struct Voice<'a> {
+    oscillator: Oscillator,
+    cursor: NoteCursor<'a>,
+    finished: bool,
+}
+
+/// Renders and mixes the tracks of a score
+pub struct Synth<'a, const N: usize> {
+    voices: [Voice<'a>; N],
+    song: Song,
+    sample_rate: u32,
+    samples: u64,
+}
+
+impl<'a, const N: usize> Synth<'a, N> {
+    /// Creates a synth that plays the first `N` tracks of `score`, each with
+    /// its own waveform
+    pub fn new(score: &Score<'a>, waveforms: [Waveform; N], sample_rate: u32) -> Self {
+        let voices = core::array::from_fn(|voice| Voice {
+            oscillator: Oscillator::new(waveforms[voice]),
+            cursor: NoteCursor::new(score.tracks.get(voice).copied().unwrap_or(&[])),
+            finished: false,
+        });
+        Self {
+            voices,
+            song: score.song,
+            sample_rate,
+            samples: 0,
+        }
+    }
+
+    /// Returns the sample rate, in Hz
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Returns whether all tracks have ended
+    pub fn finished(&self) -> bool {
+        self.voices.iter().all(|voice| voice.finished)
+    }
+
+    /// Fills `samples` with the mix of all voices, silence once the tracks
+    /// have ended
+    pub fn render(&mut self, samples: &mut [i16]) {
+        for sample in samples {
+            // Time of the sample, computed from the sample count so that
+            // the tracks do not drift
+            let now = Duration::from_micros(self.samples * 1_000_000 / self.sample_rate as u64);
+            self.samples += 1;
+
+            let mut mix = 0i32;
+            for voice in &mut self.voices {
+                voice.update(now, &self.song, self.sample_rate);
+                mix += voice.oscillator.next_sample() as i32;
+            }
+            *sample = (mix / N as i32) as i16;
+        }
+    }
+}
+
+impl Voice<'_> {
+    /// Starts and stops the notes due at `now`
+    fn update(&mut self, now: Duration, song: &Song, sample_rate: u32) {
+        if self.finished {
+            return;
+        }
+        while self.cursor.end <= now {
+            if !self.cursor.advance(song) {
+                self.oscillator.set_frequency(REST, sample_rate);
+                self.finished = true;
+                return;
+            }
+            self.oscillator.set_frequency(self.cursor.frequency, sample_rate);
+        }
+        if self.cursor.off <= now && !self.oscillator.is_silent() {
+            self.oscillator.set_frequency(REST, sample_rate);
+        }
+    }
+}
+
+/// Returns the PWM configuration for a carrier at `sample_rate` Hz
+/// with 8 bit resolution, from a `clock` Hz system clock. The divider
+/// limits the sample rate to between `clock / 1_048_560` and `clock / 256`
+/// Hz.
+pub fn pwm_config(clock: u32, sample_rate: u32) -> Result<Config, ToneError> {
+    if sample_rate == 0 {
+        return Err(ToneError::Invalid);
+    }
+    let divider = clock as f64 / (256.0 * sample_rate as f64);
+    if divider < 1.0 {
+        return Err(ToneError::TooHigh);
+    }
+    let mut config = Config::default();
+    config.top = 255;
+    config.divider = FixedU16::<U4>::checked_from_num(divider).ok_or(ToneError::TooLow)?;
+    config.compare_b = 128;
+    Ok(config)
+}
+
+/// Audio output on the B channel of a PWM slice, fed by DMA
+pub struct PwmDac<'d, C: Channel> {
+    _pwm: Pwm<'d>,
+    dma: PeripheralRef<'d, C>,
+    slice: usize,
+    buffers: [[u32; BLOCK]; 2],
+}
+
+impl<'d, C: Channel> PwmDac<'d, C> {
+    /// Creates a DAC on the B output `pin` of PWM `slice`, with a carrier
+    /// at `sample_rate` Hz, or returns an error if the PWM cannot run at
+    /// that rate
+    pub fn new<S: Slice>(
+        slice: impl Peripheral<P = S> + 'd,
+        pin: impl Peripheral<P = impl ChannelBPin<S>> + 'd,
+        dma: impl Peripheral<P = C> + 'd,
+        sample_rate: u32,
+    ) -> Result<Self, ToneError> {
+        let config = pwm_config(clk_sys_freq(), sample_rate)?;
+        let slice = slice.into_ref();
+        let number = slice.number();
+        Ok(Self {
+            _pwm: Pwm::new_output_b(slice, pin, config),
+            dma: dma.into_ref(),
+            slice: number,
+            buffers: [[128 << 16; BLOCK]; 2],
+        })
+    }
+
+    /// Plays `synth` until all its tracks have ended
+    pub async fn play<const N: usize>(&mut self, synth: &mut Synth<'_, N>) {
+        let target = pac::PWM.ch(self.slice).cc().as_ptr() as *mut u32;
+        let dreq = TreqSel::from_bits(TreqSel::PWM_WRAP0.to_bits() + self.slice as u8);
+
+        let mut current = 0;
+        fill(&mut self.buffers[current], synth);
+        loop {
+            let buffer: *const [u32] = &self.buffers[current];
+            // SAFETY: the buffer is not written until the transfer is
+            // awaited, the other buffer is filled meanwhile
+            let transfer = unsafe { dma::write(self.dma.reborrow(), buffer, target, dreq) };
+            let done = synth.finished();
+            if !done {
+                fill(&mut self.buffers[1 - current], synth);
+            }
+            transfer.await;
+            if done {
+                break;
+            }
+            current = 1 - current;
+        }
+        // Idle at half duty cycle, the middle of the signal
+        pac::PWM.ch(self.slice).cc().modify(|cc| cc.set_b(128));
+    }
+}
+
+/// Renders a block of `synth` as compare values of the B channel
+fn fill<const N: usize>(buffer: &mut [u32; BLOCK], synth: &mut Synth<'_, N>) {
+    let mut samples = [0i16; BLOCK];
+    synth.render(&mut samples);
+    for (word, sample) in buffer.iter_mut().zip(samples) {
+        let duty = ((sample as i32 + 0x8000) >> 8) as u32;
+        *word = duty << 16;
+    }
+}
+
+/// Computes one period of a sine at compile time, `core` has no `sin`
+const fn sine_table() -> [i16; TABLE_LEN] {
+    const PI: f64 = core::f64::consts::PI;
+    let mut table = [0; TABLE_LEN];
+    let mut i = 0;
+    while i < TABLE_LEN {
+        // Taylor series around 0, on -π..π
+        let mut x = 2.0 * PI * i as f64 / TABLE_LEN as f64;
+        if x > PI {
+            x -= 2.0 * PI;
+        }
+        let mut term = x;
+        let mut sum = x;
+        let mut n = 1;
+        while n < 12 {
+            term = -term * x * x / ((2 * n) * (2 * n + 1)) as f64;
+            sum += term;
+            n += 1;
+        }
+        table[i] = (sum * i16::MAX as f64) as i16;
+        i += 1;
+    }
+    table
+}
@@ -0,0 +1,143 @@
+//! PWM divider and top solver for tone frequencies
+//!
+//! A PWM slice counts from 0 to `top` at `clk_sys / divider`, so the output
+//! frequency is `clk_sys / (divider * (top + 1))`. The divider is an 8.4
+//! fixed point number between 1 and 255 + 15/16 and `top` is 16 bit. The
+//! solver starts at the smallest divider that lets `top` fit, where the
+//! rounding error is already below half a divider step, and checks the
+//! next [`SEARCH`] dividers for a closer pair.
+
+use fixed::types::extra::U4;
+use fixed::FixedU16;
+
+/// Smallest divider, 1.0 in 8.4 fixed point
+const DIV_MIN: u64 = 1 << 4;
+/// Largest divider, 255 + 15/16 in 8.4 fixed point
+const DIV_MAX: u64 = (1 << 12) - 1;
+/// Largest period, in counter steps (`top + 1`)
+const WRAP_MAX: u64 = 1 << 16;
+/// Dividers checked above the smallest one, one whole divider step
+const SEARCH: u64 = 16;
+
+/// Frequency that the PWM cannot produce
+#[derive(Debug, Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum ToneError {
+    /// The frequency is not a positive number
+    Invalid,
+    /// The frequency is below the slowest PWM period
+    TooLow,
+    /// The frequency is above the fastest PWM period
+    TooHigh,
+}
+
+/// PWM settings for a tone
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Tone {
+    /// Clock divider
+    pub divider: FixedU16<U4>,
+    /// Counter wrap value
+    pub top: u16,
+    /// Frequency actually produced, in Hz
+    pub frequency: f64,
+}
+
+impl Tone {
+    /// Returns the compare value for a 50% duty cycle
+    pub fn half_duty(&self) -> u16 {
+        self.top.div_ceil(2)
+    }
+}
+
+/// Returns the divider and top that produce the frequency closest to
+/// `frequency` Hz from a `clock` Hz system clock
+pub fn solve(clock: u32, frequency: f64) -> Result<Tone, ToneError> {
+    if frequency.is_nan() || frequency <= 0.0 {
+        return Err(ToneError::Invalid);
+    }
+
+    // Counter steps per period, in 1/16 of a divider step
+    let steps = clock as f64 * 16.0 / frequency;
+    if steps > (DIV_MAX * WRAP_MAX) as f64 {
+        return Err(ToneError::TooLow);
+    }
+    let steps = (steps + 0.5) as u64;
+    if steps < DIV_MIN * 2 {
+        return Err(ToneError::TooHigh);
+    }
+
+    let first = steps.div_ceil(WRAP_MAX).max(DIV_MIN);
+    let mut best = (0, 0, u64::MAX);
+    for div in first..=(first + SEARCH).min(DIV_MAX) {
+        let wrap = (steps + div / 2) / div;
+        if wrap < 2 {
+            break;
+        }
+        let error = steps.abs_diff(div * wrap);
+        if error < best.2 {
+            best = (div, wrap, error);
+            if error == 0 {
+                break;
+            }
+        }
+    }
+
+    let (div, wrap, _) = best;
+    Ok(Tone {
+        divider: FixedU16::from_bits(div as u16),
+        top: (wrap - 1) as u16,
+        frequency: clock as f64 * 16.0 / (div * wrap) as f64,
+    })
+}
+
+/// Returns the PWM settings for `frequency` Hz at the current system clock
+#[cfg(not(feature = "std"))]
+pub fn solve_sys(frequency: f64) -> Result<Tone, ToneError> {
+    solve(embassy_rp::clocks::clk_sys_freq(), frequency)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::{NOTE_A4, NOTE_B0, NOTE_DS8};
+
+    const CLOCK: u32 = 150_000_000;
+
+    /// Returns the frequency error of `tone` in cents
+    fn cents(tone: &Tone, frequency: f64) -> f64 {
+        1200.0 * (tone.frequency / frequency).log2()
+    }
+
+    #[test]
+    fn low_note_fits_top() {
+        // 150 MHz / 30.87 Hz is about 4.86 million steps, far above 16 bit
+        let tone = solve(CLOCK, NOTE_B0).unwrap();
+        assert!(tone.divider > 1);
+        assert!(cents(&tone, NOTE_B0).abs() < 0.1);
+    }
+
+    #[test]
+    fn exact_frequency() {
+        // 150 MHz / 1000 Hz = 1.5 * 100 000 steps
+        let tone = solve(CLOCK, 1000.0).unwrap();
+        assert_eq!(tone.frequency, 1000.0);
+        let wrap = tone.top as f64 + 1.0;
+        assert_eq!(tone.divider.to_num::<f64>() * wrap, 150_000.0);
+    }
+
+    #[test]
+    fn notes_are_close() {
+        for frequency in [NOTE_A4, NOTE_DS8] {
+            let tone = solve(CLOCK, frequency).unwrap();
+            assert!(cents(&tone, frequency).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(solve(CLOCK, 0.0), Err(ToneError::Invalid));
+        assert_eq!(solve(CLOCK, -440.0), Err(ToneError::Invalid));
+        assert_eq!(solve(CLOCK, f64::NAN), Err(ToneError::Invalid));
+        assert_eq!(solve(CLOCK, 1.0), Err(ToneError::TooLow));
+        assert_eq!(solve(CLOCK, 100_000_000.0), Err(ToneError::TooHigh));
+    }
+}
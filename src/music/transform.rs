@@ -0,0 +1,291 @@
+//! Melody transforms
+//!
+//! The transforms work on `(frequency, divider)` melodies like the ones in
+//! `sing.rs` and return iterators, so they chain without allocating:
+//!
+//! ```ignore
+//! let alert = transform::clamp(transform::transpose(melody.iter().copied(), 12), 1000.0, 4000.0);
+//! player.play_iter(alert, &transform::scale_tempo(&song, 200)).await;
+//! ```
+//!
+//! Rests are kept as they are by all transforms.
+//!
+//! [`transpose`] and [`invert`] move notes by exact intervals, leaving the
+//! key of the melody. Their diatonic versions move notes along the scale of
+//! a [`Key`] instead, so a melody in C major stays in C major:
+//!
+//! ```ignore
+//! let answer = transform::transpose_diatonic(melody.iter().copied(), Key::C_MAJOR, 4);
+//! ```
+
+use super::pitch::{Key, Pitch, Tuning, semitone_ratio};
+use super::{REST, Song};
+
+/// Transposes a melody by `semitones`, up if positive and down if negative
+pub fn transpose<I>(melody: I, semitones: i32) -> impl Iterator<Item = (f64, i16)>
+where
+    I: IntoIterator<Item = (f64, i16)>,
+{
+    let ratio = semitone_ratio(semitones);
+    map_notes(melody, move |note| note * ratio)
+}
+
+/// Transposes a melody by whole octaves
+pub fn transpose_octaves<I>(melody: I, octaves: i32) -> impl Iterator<Item = (f64, i16)>
+where
+    I: IntoIterator<Item = (f64, i16)>,
+{
+    transpose(melody, octaves * 12)
+}
+
+/// Moves each note by whole octaves into `low..=high` Hz, so that the note
+/// names are kept. The range should span at least an octave, notes that
+/// cannot fit are set to the closest bound. Notes that are not positive
+/// finite frequencies, or all notes if the range is not `0 < low <= high`,
+/// are left unchanged.
+pub fn clamp<I>(melody: I, low: f64, high: f64) -> impl Iterator<Item = (f64, i16)>
+where
+    I: IntoIterator<Item = (f64, i16)>,
+{
+    let valid = low > 0.0 && low.is_finite() && low <= high;
+    map_notes(melody, move |note| {
+        if !(valid && note > 0.0 && note.is_finite()) {
+            return note;
+        }
+        let mut note = note;
+        while note < low {
+            note *= 2.0;
+        }
+        while note > high {
+            note /= 2.0;
+        }
+        note.clamp(low, high)
+    })
+}
+
+/// Mirrors the pitches of a melody around `axis` Hz, an interval up from the
+/// axis becomes the same interval down
+pub fn invert<I>(melody: I, axis: f64) -> impl Iterator<Item = (f64, i16)>
+where
+    I: IntoIterator<Item = (f64, i16)>,
+{
+    map_notes(melody, move |note| axis * axis / note)
+}
+
+/// Transposes a melody by `steps` degrees of the scale of `key`, up if
+/// positive and down if negative: a third up is E from C and F from D in C
+/// major. Notes that would leave the MIDI range are kept as they are.
+pub fn transpose_diatonic<I>(melody: I, key: Key, steps: i32) -> impl Iterator<Item = (f64, i16)>
+where
+    I: IntoIterator<Item = (f64, i16)>,
+{
+    map_pitches(melody, move |pitch| key.transpose(pitch, steps))
+}
+
+/// Mirrors the pitches of a melody around `axis` in the scale of `key`, a
+/// third up from the axis becomes a third down so that the melody stays in
+/// the key. Notes that would leave the MIDI range are kept as they are.
+pub fn invert_diatonic<I>(melody: I, key: Key, axis: Pitch) -> impl Iterator<Item = (f64, i16)>
+where
+    I: IntoIterator<Item = (f64, i16)>,
+{
+    map_pitches(melody, move |pitch| key.invert(pitch, axis))
+}
+
+/// Plays a melody backwards, also known as retrograde
+pub fn reverse<I>(melody: I) -> impl Iterator<Item = (f64, i16)>
+where
+    I: IntoIterator<Item = (f64, i16)>,
+    I::IntoIter: DoubleEndedIterator,
+{
+    melody.into_iter().rev()
+}
+
+/// Plays a melody backwards and inverted around `axis` Hz
+pub fn retrograde_inversion<I>(melody: I, axis: f64) -> impl Iterator<Item = (f64, i16)>
+where
+    I: IntoIterator<Item = (f64, i16)>,
+    I::IntoIter: DoubleEndedIterator,
+{
+    invert(reverse(melody), axis)
+}
+
+/// Returns the song at `percent` of its tempo, 200 plays twice as fast
+pub fn scale_tempo(song: &Song, percent: u16) -> Song {
+    let tempo = (song.tempo() as u32 * percent as u32 / 100).clamp(1, u16::MAX as u32);
+    let mut song = *song;
+    song.set_tempo(tempo as u16);
+    song
+}
+
+/// Moves every note that is not a rest to the pitch returned by `f` for its
+/// nearest pitch, keeping how far it was tuned from that pitch
+fn map_pitches<I, F>(melody: I, f: F) -> impl Iterator<Item = (f64, i16)>
+where
+    I: IntoIterator<Item = (f64, i16)>,
+    F: Fn(Pitch) -> Option<Pitch>,
+{
+    map_notes(melody, move |note| {
+        let Some(pitch) = Pitch::nearest(note, Tuning::STANDARD) else {
+            return note;
+        };
+        match f(pitch) {
+            Some(moved) => note * semitone_ratio(moved.midi() as i32 - pitch.midi() as i32),
+            None => note,
+        }
+    })
+}
+
+/// Applies `f` to the frequency of every note that is not a rest
+fn map_notes<I, F>(melody: I, f: F) -> impl Iterator<Item = (f64, i16)>
+where
+    I: IntoIterator<Item = (f64, i16)>,
+    F: Fn(f64) -> f64,
+{
+    melody.into_iter().map(move |(note, divider)| {
+        if note == REST {
+            (note, divider)
+        } else {
+            (f(note), divider)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::{
+        Accidental, Mode, NOTE_A4, NOTE_B0, NOTE_C4, NOTE_CS4, NOTE_D4, NOTE_E4, NOTE_FS4, NOTE_G4,
+        NoteName,
+    };
+
+    /// Returns the pitches of `melody`, `None` for rests
+    fn pitches(melody: impl Iterator<Item = (f64, i16)>) -> heapless::Vec<Option<Pitch>, 16> {
+        melody
+            .map(|(note, _)| Pitch::nearest(note, Tuning::STANDARD))
+            .collect()
+    }
+
+    fn parse(names: &[&str]) -> heapless::Vec<Option<Pitch>, 16> {
+        names.iter().map(|name| name.parse().ok()).collect()
+    }
+
+    const SCALE: [(f64, i16); 5] = [
+        (NOTE_C4, 4),
+        (NOTE_D4, 4),
+        (REST, 4),
+        (NOTE_E4, 4),
+        (NOTE_G4, 2),
+    ];
+
+    #[test]
+    fn nearest_pitch() {
+        assert_eq!(Pitch::nearest(NOTE_A4, Tuning::STANDARD), Some(Pitch::A4));
+        assert_eq!(Pitch::nearest(445.0, Tuning::STANDARD), Some(Pitch::A4));
+        assert_eq!(Pitch::nearest(435.0, Tuning::STANDARD), Some(Pitch::A4));
+        assert_eq!(Pitch::nearest(REST, Tuning::STANDARD), None);
+        assert_eq!(Pitch::nearest(20_000.0, Tuning::STANDARD), None);
+        for midi in 0..=127 {
+            let pitch = Pitch::from_midi(midi).unwrap();
+            assert_eq!(
+                Pitch::nearest(pitch.frequency(Tuning::STANDARD), Tuning::STANDARD),
+                Some(pitch)
+            );
+        }
+    }
+
+    #[test]
+    fn degrees() {
+        let c4 = Pitch::C4;
+        assert_eq!(Key::C_MAJOR.degree(c4), (35, 0));
+        assert_eq!(Key::C_MAJOR.degree("C#4".parse().unwrap()), (35, 1));
+        assert_eq!(Key::C_MAJOR.degree("B3".parse().unwrap()), (34, 0));
+        assert_eq!(Key::A_MINOR.degree(c4), (30, 0));
+        assert_eq!(Key::C_MAJOR.pitch(35, 1), "C#4".parse().ok());
+        assert_eq!(Key::C_MAJOR.pitch(-1, 0), None);
+    }
+
+    #[test]
+    fn diatonic_transpose_stays_in_key() {
+        let third = transpose_diatonic(SCALE, Key::C_MAJOR, 2);
+        assert_eq!(pitches(third), parse(&["E4", "F4", "", "G4", "B4"]));
+
+        let down = transpose_diatonic(SCALE, Key::C_MAJOR, -7);
+        assert_eq!(pitches(down), parse(&["C3", "D3", "", "E3", "G3"]));
+
+        let g_major = Key::new(NoteName::G, Accidental::Natural, Mode::Major);
+        let fifth = transpose_diatonic(SCALE, g_major, 4);
+        assert_eq!(pitches(fifth), parse(&["G4", "A4", "", "B4", "D5"]));
+
+        let minor = transpose_diatonic(SCALE, Key::A_MINOR, 1);
+        assert_eq!(pitches(minor), parse(&["D4", "E4", "", "F4", "A4"]));
+    }
+
+    #[test]
+    fn diatonic_transpose_keeps_accidentals_and_tuning() {
+        let melody = [(NOTE_CS4, 4), (NOTE_FS4, 4)];
+        let up = transpose_diatonic(melody, Key::C_MAJOR, 1);
+        assert_eq!(pitches(up), parse(&["D#4", "G#4"]));
+
+        // A4 at 442 Hz moved a fourth up is D5 at 442 Hz
+        let (note, divider) = transpose_diatonic([(442.0, 8)], Key::C_MAJOR, 3)
+            .next()
+            .unwrap();
+        let d5 = Pitch::parse("D5").unwrap().frequency(Tuning { a4: 442.0 });
+        assert!((note - d5).abs() < 1e-9);
+        assert_eq!(divider, 8);
+    }
+
+    #[test]
+    fn diatonic_inversion() {
+        let inverted = invert_diatonic(SCALE, Key::C_MAJOR, Pitch::C4);
+        assert_eq!(pitches(inverted), parse(&["C4", "B3", "", "A3", "F3"]));
+
+        let around_e = invert_diatonic(SCALE, Key::C_MAJOR, "E4".parse().unwrap());
+        assert_eq!(pitches(around_e), parse(&["G4", "F4", "", "E4", "C4"]));
+    }
+
+    #[test]
+    fn out_of_range_notes_are_kept() {
+        let melody = [(NOTE_B0, 4)];
+        let (note, _) = transpose_diatonic(melody, Key::C_MAJOR, -70)
+            .next()
+            .unwrap();
+        assert_eq!(note, NOTE_B0);
+        assert_eq!(
+            transpose_diatonic([(REST, 4)], Key::C_MAJOR, 3).next(),
+            Some((REST, 4))
+        );
+    }
+
+    #[test]
+    fn clamp_into_range() {
+        let melody = [(NOTE_C4, 4), (NOTE_A4 * 8.0, 4), (NOTE_B0, 4), (REST, 4)];
+        let notes: heapless::Vec<f64, 4> =
+            clamp(melody, 250.0, 1000.0).map(|(note, _)| note).collect();
+        assert_eq!(notes, [NOTE_C4, NOTE_A4 * 2.0, NOTE_B0 * 16.0, REST]);
+
+        // Notes that cannot fit a range narrower than an octave are clamped
+        let (note, _) = clamp([(NOTE_C4, 4)], 300.0, 400.0).next().unwrap();
+        assert_eq!(note, 300.0);
+    }
+
+    #[test]
+    fn clamp_invalid_input_terminates() {
+        for note in [f64::INFINITY, f64::NAN, -NOTE_A4] {
+            let (clamped, _) = clamp([(note, 4)], 250.0, 1000.0).next().unwrap();
+            assert_eq!(clamped.to_bits(), note.to_bits());
+        }
+        for (low, high) in [
+            (0.0, 1000.0),
+            (-250.0, 1000.0),
+            (250.0, 0.0),
+            (1000.0, 250.0),
+        ] {
+            let (note, _) = clamp([(NOTE_A4, 4)], low, high).next().unwrap();
+            assert_eq!(note, NOTE_A4);
+        }
+        let (note, _) = clamp([(NOTE_A4, 4)], f64::NAN, 1000.0).next().unwrap();
+        assert_eq!(note, NOTE_A4);
+    }
+}
@@ -0,0 +1,331 @@
+//! Pitch detection for a tuner
+//!
+//! [`Yin`] estimates the fundamental frequency of a block of samples with
+//! the YIN algorithm: the cumulative mean normalized difference of the
+//! signal with itself is computed for every lag, and the first dip below a
+//! threshold is the period. The lag is refined with a parabola through the
+//! dip and its neighbours, then again at the largest multiple of the period
+//! that fits in the block, which divides the interpolation error of short
+//! periods. [`Reading::new`] then finds the nearest note and how many cents
+//! the frequency is away from it.
+//!
+//! The differences are computed on the fly, so no buffer is needed beside
+//! the samples. Only `core` is used, the module also builds on the host.
+
+use super::pitch::{Pitch, Tuning, semitone_ratio};
+
+/// Pitch detector
+#[derive(Debug, Copy, Clone, PartialEq, defmt::Format)]
+pub struct Yin {
+    /// Dips of the normalized difference below this value are periods,
+    /// 0.1 to 0.2 works for most instruments
+    pub threshold: f32,
+    /// Lowest frequency detected, in Hz
+    pub min_frequency: f32,
+    /// Highest frequency detected, in Hz
+    pub max_frequency: f32,
+    /// Mean square of the signal, without its DC offset, below which the
+    /// block is silence
+    pub min_power: f32,
+}
+
+impl Yin {
+    /// Covers the guitar and the voice, E2 to C6
+    pub const GUITAR: Yin = Yin::new(70.0, 1100.0);
+
+    /// Creates a detector for frequencies between `min_frequency` and
+    /// `max_frequency`
+    pub const fn new(min_frequency: f32, max_frequency: f32) -> Self {
+        Self {
+            threshold: 0.15,
+            min_frequency,
+            max_frequency,
+            min_power: 100.0,
+        }
+    }
+
+    /// Returns the fundamental frequency of `samples` in Hz, `None` for
+    /// silence or noise. The block must hold at least two periods of the
+    /// lowest frequency.
+    pub fn detect(&self, samples: &[i16], sample_rate: u32) -> Option<f32> {
+        if power(samples) < self.min_power {
+            return None;
+        }
+
+        let rate = sample_rate as f32;
+        let window = samples.len() / 2;
+        let min_lag = ((rate / self.max_frequency) as usize).max(2);
+        let max_lag = ((rate / self.min_frequency) as usize + 1).min(window.saturating_sub(1));
+        if min_lag >= max_lag {
+            return None;
+        }
+
+        // Differences at the two previous lags, and the normalized one of
+        // the last lag
+        let mut previous = [0.0f32; 2];
+        let mut dip = 1.0;
+        let mut sum = 0.0;
+        for lag in 1..=max_lag {
+            let difference = difference(samples, window, lag);
+            sum += difference;
+            let normalized = if sum > 0.0 {
+                difference * lag as f32 / sum
+            } else {
+                1.0
+            };
+
+            // The previous lag is a dip below the threshold once the
+            // difference stops falling. The normalization tilts the curve,
+            // so the raw differences are interpolated.
+            let [before, at] = previous;
+            if lag > min_lag && dip < self.threshold && normalized >= dip {
+                let period = (lag - 1) as f32 + parabola(before, at, difference);
+                return Some(rate / refine(samples, window, period, max_lag));
+            }
+            previous = [at, difference];
+            dip = normalized;
+        }
+        None
+    }
+}
+
+impl Default for Yin {
+    fn default() -> Self {
+        Self::GUITAR
+    }
+}
+
+/// Nearest note of a detected frequency
+#[derive(Debug, Copy, Clone, PartialEq, defmt::Format)]
+pub struct Reading {
+    /// Detected frequency in Hz
+    pub frequency: f32,
+    /// Nearest note
+    pub pitch: Pitch,
+    /// Frequency of the nearest note, the matching `NOTE_*` constant with
+    /// the standard tuning
+    pub note: f64,
+    /// Deviation from the nearest note in cents, positive when sharp
+    pub cents: f32,
+}
+
+impl Reading {
+    /// Finds the nearest note of `frequency`, `None` outside of the MIDI
+    /// range
+    pub fn new(frequency: f32, tuning: Tuning) -> Option<Reading> {
+        if !frequency.is_finite() || frequency <= 0.0 {
+            return None;
+        }
+        // Octaves and semitones from A4, with `ratio` in [1, 2)
+        let mut ratio = frequency as f64 / tuning.a4;
+        let mut octaves = 0;
+        while ratio >= 2.0 {
+            ratio /= 2.0;
+            octaves += 1;
+        }
+        while ratio < 1.0 {
+            ratio *= 2.0;
+            octaves -= 1;
+        }
+        let (semitones, cents) = (0..=12)
+            .map(|semitones| (semitones, cents(ratio / semitone_ratio(semitones))))
+            .min_by(|(_, a), (_, b)| magnitude(*a).total_cmp(&magnitude(*b)))?;
+
+        let midi = 69 + 12 * octaves + semitones;
+        let pitch = Pitch::from_midi(u8::try_from(midi).ok()?)?;
+        Some(Reading {
+            frequency,
+            pitch,
+            note: pitch.frequency(tuning),
+            cents: cents as f32,
+        })
+    }
+
+    /// Returns whether the frequency is within `tolerance` cents of the note
+    pub fn in_tune(&self, tolerance: f32) -> bool {
+        magnitude(self.cents as f64) <= tolerance as f64
+    }
+}
+
+/// Returns the mean square of the samples without their DC offset
+fn power(samples: &[i16]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let count = samples.len() as f32;
+    let mean = samples.iter().map(|&s| s as f32).sum::<f32>() / count;
+    samples
+        .iter()
+        .map(|&s| {
+            let centered = s as f32 - mean;
+            centered * centered
+        })
+        .sum::<f32>()
+        / count
+}
+
+/// Returns the squared difference of the first `window` samples with the
+/// ones `lag` samples later
+fn difference(samples: &[i16], window: usize, lag: usize) -> f32 {
+    samples[..window]
+        .iter()
+        .zip(&samples[lag..lag + window])
+        .map(|(&a, &b)| {
+            let delta = a as f32 - b as f32;
+            delta * delta
+        })
+        .sum()
+}
+
+/// Refines `period` with the dip at its largest multiple below `max_lag`
+fn refine(samples: &[i16], window: usize, period: f32, max_lag: usize) -> f32 {
+    let multiple = ((max_lag - 1) as f32 / period) as usize;
+    if multiple < 2 {
+        return period;
+    }
+    // The estimate is within half a sample of the dip, its multiple can be
+    // a sample off
+    let mut lag = (period * multiple as f32 + 0.5) as usize;
+    let mut at = difference(samples, window, lag);
+    let mut before = difference(samples, window, lag - 1);
+    let mut after = difference(samples, window, lag + 1);
+    if before < at && lag > 2 {
+        (lag, after, at) = (lag - 1, at, before);
+        before = difference(samples, window, lag - 1);
+    } else if after < at && lag + 1 < max_lag {
+        (lag, before, at) = (lag + 1, at, after);
+        after = difference(samples, window, lag + 1);
+    }
+    (lag as f32 + parabola(before, at, after)) / multiple as f32
+}
+
+/// Returns the offset of the minimum of the parabola through three points
+/// around the middle one, between -1 and 1
+fn parabola(before: f32, middle: f32, after: f32) -> f32 {
+    let curvature = before - 2.0 * middle + after;
+    if curvature <= 0.0 {
+        return 0.0;
+    }
+    ((before - after) / (2.0 * curvature)).clamp(-1.0, 1.0)
+}
+
+/// Returns the interval of a frequency ratio in cents, 1200 log2(ratio),
+/// accurate for ratios between 1/2 and 2
+fn cents(ratio: f64) -> f64 {
+    // ln(x) = 2 atanh((x - 1) / (x + 1)), |y| <= 1/3 here
+    let y = (ratio - 1.0) / (ratio + 1.0);
+    let y2 = y * y;
+    let ln = 2.0 * y * (1.0 + y2 * (1.0 / 3.0 + y2 * (1.0 / 5.0 + y2 * (1.0 / 7.0 + y2 / 9.0))));
+    ln * (1200.0 / core::f64::consts::LN_2)
+}
+
+/// Returns the absolute value, `core` has no `f64::abs`
+fn magnitude(x: f64) -> f64 {
+    if x < 0.0 { -x } else { x }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::{NOTE_A2, NOTE_A4, NOTE_C6, NOTE_E2};
+
+    const RATE: u32 = 8000;
+    const BLOCK: usize = 1024;
+
+    /// Returns a block of a sine at `frequency` Hz
+    fn sine(frequency: f64) -> [i16; BLOCK] {
+        core::array::from_fn(|i| {
+            let phase = i as f64 * frequency / RATE as f64;
+            (8000.0 * (2.0 * core::f64::consts::PI * phase).sin()) as i16
+        })
+    }
+
+    /// Returns a block of a rising saw tooth at `frequency` Hz, made of its
+    /// harmonics below the Nyquist frequency as after an anti-aliasing
+    /// filter
+    fn saw(frequency: f64) -> [i16; BLOCK] {
+        use core::f64::consts::PI;
+        let harmonics = (RATE as f64 / 2.0 / frequency) as u32;
+        core::array::from_fn(|i| {
+            let phase = 2.0 * PI * i as f64 * frequency / RATE as f64;
+            let sum: f64 = (1..=harmonics)
+                .map(|k| (k as f64 * phase).sin() / k as f64 * if k % 2 == 0 { -1.0 } else { 1.0 })
+                .sum();
+            (8000.0 * 2.0 / PI * sum) as i16
+        })
+    }
+
+    /// Returns the interval between two frequencies in cents
+    fn off_by(detected: f32, expected: f64) -> f64 {
+        cents(detected as f64 / expected)
+    }
+
+    #[test]
+    fn detects_sines_and_saws() {
+        let yin = Yin::GUITAR;
+        for frequency in [82.41, 110.0, 440.0, 1046.5] {
+            for (shape, block) in [("sine", sine(frequency)), ("saw", saw(frequency))] {
+                let detected = yin.detect(&block, RATE);
+                let detected = detected.unwrap_or_else(|| panic!("{shape} at {frequency} Hz"));
+                let off = off_by(detected, frequency);
+                assert!(
+                    magnitude(off) < 2.0,
+                    "{shape} at {frequency} Hz: {off} cents"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn silence_and_noise_have_no_pitch() {
+        let yin = Yin::GUITAR;
+        assert_eq!(yin.detect(&[0; BLOCK], RATE), None);
+        assert_eq!(yin.detect(&[1000; BLOCK], RATE), None);
+
+        // xorshift32
+        let mut state = 0x1234_5678u32;
+        let noise: [i16; BLOCK] = core::array::from_fn(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            (state >> 16) as i16 / 4
+        });
+        assert_eq!(yin.detect(&noise, RATE), None);
+    }
+
+    #[test]
+    fn snaps_to_the_nearest_note() {
+        let reading = Reading::new(NOTE_A4 as f32, Tuning::STANDARD).unwrap();
+        assert_eq!((reading.pitch, reading.note), (Pitch::A4, NOTE_A4));
+        assert!(magnitude(reading.cents as f64) < 0.01);
+
+        // 20 cents sharp and flat
+        let sharp = Reading::new((NOTE_E2 * 1.011_619) as f32, Tuning::STANDARD).unwrap();
+        assert_eq!(sharp.note, NOTE_E2);
+        assert!((sharp.cents - 20.0).abs() < 0.1, "{}", sharp.cents);
+        let flat = Reading::new((NOTE_A2 / 1.011_619) as f32, Tuning::STANDARD).unwrap();
+        assert_eq!(flat.note, NOTE_A2);
+        assert!((flat.cents + 20.0).abs() < 0.1, "{}", flat.cents);
+
+        // 40 cents above B5 is still B5, 60 cents above is C6
+        let b5 = NOTE_C6 / semitone_ratio(1);
+        let below = Reading::new((b5 * 1.023_374) as f32, Tuning::STANDARD).unwrap();
+        assert_eq!(below.pitch, "B5".parse().unwrap());
+        assert!(below.cents > 0.0 && !below.in_tune(30.0));
+        let above = Reading::new((b5 * 1.035_265) as f32, Tuning::STANDARD).unwrap();
+        assert_eq!(above.note, NOTE_C6);
+        assert!((above.cents + 40.0).abs() < 0.1, "{}", above.cents);
+        assert!(above.in_tune(45.0));
+    }
+
+    #[test]
+    fn invalid_frequencies() {
+        for frequency in [0.0, -440.0, f32::NAN, f32::INFINITY, 20_000.0] {
+            assert_eq!(
+                Reading::new(frequency, Tuning::STANDARD),
+                None,
+                "{frequency}"
+            );
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! WAV rendering of melodies on the host
+//!
+//! Only built with the `std` feature. The renderer schedules the notes like
+//! the buzzer player does, 90% sound and 10% silence at the tempo of the
+//! song, and plays the square wave of the frequency the PWM really
+//! produces, so a wrong note or duration can be heard before flashing.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use embassy_time::Duration;
+
+use super::envelope::Articulation;
+use super::{REST, Song, tone};
+
+/// Sample rate of the rendered files, in Hz
+pub const SAMPLE_RATE: u32 = 44_100;
+
+/// System clock of the RP2350 that drives the PWM, in Hz
+const SYS_CLOCK: u32 = 150_000_000;
+
+/// Amplitude of the square wave, half of full scale
+const AMPLITUDE: i16 = i16::MAX / 2;
+
+/// A note as the player plays it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ScheduledNote {
+    /// Frequency produced by the PWM in Hz, [`REST`] for a rest
+    pub frequency: f64,
+    /// Start since the beginning of the melody
+    pub start: Duration,
+    /// Time the note sounds
+    pub sound: Duration,
+    /// Length of the note, including the silence after it
+    pub length: Duration,
+}
+
+/// Returns the notes of `melody` with their timing at the tempo of `song`
+pub fn schedule(melody: &[(f64, i16)], song: &Song) -> Vec<ScheduledNote> {
+    let mut start = Duration::from_ticks(0);
+    melody
+        .iter()
+        .map(|&(note, divider)| {
+            let length = song.divider_duration(divider);
+            let frequency = match tone::solve(SYS_CLOCK, note) {
+                Ok(tone) if note != REST => tone.frequency,
+                _ => REST,
+            };
+            let scheduled = ScheduledNote {
+                frequency,
+                start,
+                sound: Articulation::Normal.sound(length),
+                length,
+            };
+            start += length;
+            scheduled
+        })
+        .collect()
+}
+
+/// Returns the time it takes to play `melody` once
+pub fn duration(melody: &[(f64, i16)], song: &Song) -> Duration {
+    melody
+        .iter()
+        .map(|&(_, divider)| song.divider_duration(divider))
+        .fold(Duration::from_ticks(0), |total, length| total + length)
+}
+
+/// Renders `melody` as mono samples at `sample_rate` Hz
+pub fn render(melody: &[(f64, i16)], song: &Song, sample_rate: u32) -> Vec<i16> {
+    let samples_at = |time: Duration| (time.as_micros() * sample_rate as u64 / 1_000_000) as usize;
+    let mut samples = vec![0; samples_at(duration(melody, song))];
+
+    for note in schedule(melody, song) {
+        if note.frequency == REST {
+            continue;
+        }
+        let (start, end) = (samples_at(note.start), samples_at(note.start + note.sound));
+        for (i, sample) in samples[start..end].iter_mut().enumerate() {
+            // 50% duty cycle, high on the first half of each period
+            let phase = (i as f64 * note.frequency / sample_rate as f64).fract();
+            *sample = if phase < 0.5 { AMPLITUDE } else { -AMPLITUDE };
+        }
+    }
+    samples
+}
+
+/// Writes 16 bit mono PCM `samples` as a WAV file
+pub fn write_wav<W: Write>(mut writer: W, samples: &[i16], sample_rate: u32) -> io::Result<()> {
+    let data_len = (samples.len() * 2) as u32;
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(36 + data_len).to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    // PCM, 1 channel
+    writer.write_all(&1u16.to_le_bytes())?;
+    writer.write_all(&1u16.to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    // Byte rate, block alignment and bits per sample
+    writer.write_all(&(sample_rate * 2).to_le_bytes())?;
+    writer.write_all(&2u16.to_le_bytes())?;
+    writer.write_all(&16u16.to_le_bytes())?;
+
+    writer.write_all(b"data")?;
+    writer.write_all(&data_len.to_le_bytes())?;
+    for sample in samples {
+        writer.write_all(&sample.to_le_bytes())?;
+    }
+    writer.flush()
+}
+
+/// Renders `melody` at the tempo of `song` to a WAV file at `path`
+pub fn render_file<P: AsRef<Path>>(path: P, melody: &[(f64, i16)], song: &Song) -> io::Result<()> {
+    let samples = render(melody, song, SAMPLE_RATE);
+    write_wav(BufWriter::new(File::create(path)?), &samples, SAMPLE_RATE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::library::{ODE_TO_JOY, ODE_TO_JOY_BASS};
+    use crate::music::{NOTE_C3, NOTE_D4, NOTE_E4, NOTE_F4, NOTE_G2, NOTE_G4};
+
+    /// Checks that the PWM plays `actual` within 0.05% of `expected`, less
+    /// than a cent
+    fn assert_close(actual: f64, expected: f64) {
+        let error = actual / expected - 1.0;
+        assert!(error.abs() < 0.0005, "{actual} Hz instead of {expected} Hz");
+    }
+
+    #[test]
+    fn duration_of_melody_and_bass() {
+        let song = ODE_TO_JOY.song();
+        assert_eq!(duration(&ODE_TO_JOY.notes, &song), Duration::from_secs(8));
+        assert_eq!(duration(&ODE_TO_JOY_BASS.notes, &song), Duration::from_secs(8));
+    }
+
+    #[test]
+    fn schedule_of_melody() {
+        let notes = schedule(&ODE_TO_JOY.notes, &ODE_TO_JOY.song());
+        assert_eq!(notes.len(), 15);
+
+        let expected = [NOTE_E4, NOTE_E4, NOTE_F4, NOTE_G4, NOTE_G4, NOTE_F4];
+        for (note, expected) in notes.iter().zip(expected) {
+            assert_close(note.frequency, expected);
+        }
+        assert_eq!(notes[0].start, Duration::from_ticks(0));
+        assert_eq!(notes[0].length, Duration::from_millis(500));
+        assert_eq!(notes[0].sound, Duration::from_millis(450));
+        assert_eq!(notes[1].start, Duration::from_millis(500));
+
+        // Dotted quarter and eighth
+        assert_eq!(notes[12].start, Duration::from_secs(6));
+        assert_eq!(notes[12].length, Duration::from_millis(750));
+        assert_eq!(notes[13].start, Duration::from_millis(6750));
+        assert_eq!(notes[13].length, Duration::from_millis(250));
+
+        let last = notes[14];
+        assert_close(last.frequency, NOTE_D4);
+        assert_eq!(last.start + last.length, Duration::from_secs(8));
+    }
+
+    #[test]
+    fn schedule_of_bass() {
+        let notes = schedule(&ODE_TO_JOY_BASS.notes, &ODE_TO_JOY_BASS.song());
+        let expected = [NOTE_C3, NOTE_G2, NOTE_C3, NOTE_G2];
+        assert_eq!(notes.len(), expected.len());
+        for (i, (note, expected)) in notes.iter().zip(expected).enumerate() {
+            assert_close(note.frequency, expected);
+            assert_eq!(note.start, Duration::from_secs(2 * i as u64));
+            assert_eq!(note.sound, Duration::from_millis(1800));
+        }
+    }
+}
@@ -0,0 +1,379 @@
+//! Rolling min/max/mean/stddev statistics for sensor readings
+//!
+//! Readings are aggregated per minute and per hour. The last hour of
+//! minutes and the last day of hours are kept in fixed-capacity buffers, so
+//! the history never allocates and older data is dropped first.
+
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_time::{Duration, Instant};
+use heapless::Deque;
+
+/// Number of per-minute aggregates kept
+pub const MINUTES: usize = 60;
+/// Number of per-hour aggregates kept
+pub const HOURS: usize = 24;
+
+/// A history shared between the task that samples and the tasks that query it
+pub type SharedHistory = Mutex<CriticalSectionRawMutex, RefCell<History>>;
+
+/// Summary of a set of readings
+#[derive(Debug, Copy, Clone, PartialEq, defmt::Format)]
+pub struct Summary {
+    /// Lowest reading
+    pub min: f64,
+    /// Highest reading
+    pub max: f64,
+    /// Average
+    pub mean: f64,
+    /// Population standard deviation
+    pub stddev: f64,
+    /// Number of readings
+    pub count: u32,
+}
+
+/// Running aggregate of readings that can be merged with other aggregates
+///
+/// The mean and the sum of squared deviations are updated incrementally
+/// (Welford), which stays exact for constant input where a sum of squares
+/// would cancel.
+#[derive(Debug, Copy, Clone)]
+pub struct Stats {
+    min: f64,
+    max: f64,
+    mean: f64,
+    deviations: f64,
+    count: u32,
+}
+
+impl Stats {
+    /// Creates an empty aggregate
+    pub const fn new() -> Self {
+        Self {
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            mean: 0.0,
+            deviations: 0.0,
+            count: 0,
+        }
+    }
+
+    /// Adds a reading
+    pub fn push(&mut self, value: f64) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.deviations += delta * (value - self.mean);
+    }
+
+    /// Adds all readings of `other`
+    pub fn merge(&mut self, other: &Stats) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            *self = *other;
+            return;
+        }
+        let (a, b) = (self.count as f64, other.count as f64);
+        let delta = other.mean - self.mean;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.mean += delta * b / (a + b);
+        self.deviations += other.deviations + delta * delta * a * b / (a + b);
+        self.count += other.count;
+    }
+
+    /// Returns the number of readings
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Returns the summary, or `None` if there are no readings
+    pub fn summary(&self) -> Option<Summary> {
+        if self.count == 0 {
+            return None;
+        }
+        let variance = self.deviations / self.count as f64;
+        Some(Summary {
+            min: self.min,
+            max: self.max,
+            mean: self.mean,
+            stddev: sqrt(variance),
+            count: self.count,
+        })
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Per-minute and per-hour history of readings
+pub struct History {
+    minute: Stats,
+    minute_start: Option<Instant>,
+    hour: Stats,
+    hour_minutes: usize,
+    minutes: Deque<Stats, MINUTES>,
+    hours: Deque<Stats, HOURS>,
+}
+
+impl History {
+    /// Creates an empty history
+    pub const fn new() -> Self {
+        Self {
+            minute: Stats::new(),
+            minute_start: None,
+            hour: Stats::new(),
+            hour_minutes: 0,
+            minutes: Deque::new(),
+            hours: Deque::new(),
+        }
+    }
+
+    /// Adds a reading taken at `now`. After a gap without readings, the
+    /// minutes of the gap are closed empty.
+    pub fn push(&mut self, now: Instant, value: f64) {
+        let start = *self.minute_start.get_or_insert(now);
+        let elapsed = now.saturating_duration_since(start).as_secs() / 60;
+        if elapsed > 0 {
+            // Past a day and an hour of closed minutes, only empty ones
+            // would be added
+            for _ in 0..elapsed.min((MINUTES * (HOURS + 1)) as u64) {
+                self.close_minute();
+            }
+            self.minute_start = Some(start + Duration::from_secs(elapsed * 60));
+        }
+        self.minute.push(value);
+    }
+
+    fn close_minute(&mut self) {
+        if self.minutes.is_full() {
+            self.minutes.pop_front();
+        }
+        let _ = self.minutes.push_back(self.minute);
+        self.hour.merge(&self.minute);
+        self.minute = Stats::new();
+
+        self.hour_minutes += 1;
+        if self.hour_minutes == 60 {
+            if self.hours.is_full() {
+                self.hours.pop_front();
+            }
+            let _ = self.hours.push_back(self.hour);
+            self.hour = Stats::new();
+            self.hour_minutes = 0;
+        }
+    }
+
+    /// Returns the summary of the minute in progress
+    pub fn current_minute(&self) -> Option<Summary> {
+        self.minute.summary()
+    }
+
+    /// Returns the summaries of the completed minutes, oldest first
+    pub fn minutes(&self) -> impl Iterator<Item = Summary> + '_ {
+        self.minutes.iter().filter_map(Stats::summary)
+    }
+
+    /// Returns the summaries of the completed hours, oldest first
+    pub fn hours(&self) -> impl Iterator<Item = Summary> + '_ {
+        self.hours.iter().filter_map(Stats::summary)
+    }
+
+    /// Returns the summary of the last 60 completed minutes and the minute
+    /// in progress
+    pub fn last_hour(&self) -> Option<Summary> {
+        let mut stats = self.minute;
+        self.minutes.iter().for_each(|minute| stats.merge(minute));
+        stats.summary()
+    }
+
+    /// Returns the summary of the last 24 completed hours and the hour in
+    /// progress
+    pub fn last_day(&self) -> Option<Summary> {
+        let mut stats = self.minute;
+        stats.merge(&self.hour);
+        self.hours.iter().for_each(|hour| stats.merge(hour));
+        stats.summary()
+    }
+
+    /// Drops all readings
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Square root using Newton's method, `core` has no `f64::sqrt`
+fn sqrt(x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    let mut guess = if x > 1.0 { x / 2.0 } else { 1.0 };
+    for _ in 0..32 {
+        let next = (guess + x / guess) / 2.0;
+        if next == guess {
+            break;
+        }
+        guess = next;
+    }
+    guess
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the instant `minutes` minutes and `seconds` seconds after boot
+    fn at(minutes: u64, seconds: u64) -> Instant {
+        Instant::from_secs(minutes * 60 + seconds)
+    }
+
+    #[test]
+    fn minute_rollover() {
+        let mut history = History::new();
+        history.push(at(0, 0), 20.0);
+        history.push(at(0, 30), 22.0);
+        assert_eq!(history.minutes().count(), 0);
+        assert_eq!(history.current_minute().unwrap().count, 2);
+
+        history.push(at(1, 0), 30.0);
+        let minutes: heapless::Vec<Summary, 4> = history.minutes().collect();
+        assert_eq!(minutes.len(), 1);
+        assert_eq!(
+            (minutes[0].min, minutes[0].max, minutes[0].mean),
+            (20.0, 22.0, 21.0)
+        );
+        assert_eq!(history.current_minute().unwrap().mean, 30.0);
+    }
+
+    #[test]
+    fn in_progress_readings_count() {
+        let mut history = History::new();
+        history.push(at(0, 0), 20.0);
+        history.push(at(1, 0), 30.0);
+
+        let hour = history.last_hour().unwrap();
+        assert_eq!((hour.min, hour.max, hour.count), (20.0, 30.0, 2));
+        let day = history.last_day().unwrap();
+        assert_eq!((day.min, day.max, day.count), (20.0, 30.0, 2));
+    }
+
+    #[test]
+    fn hour_rollover() {
+        let mut history = History::new();
+        for minute in 0..=60 {
+            history.push(at(minute, 0), minute as f64);
+        }
+        let hours: heapless::Vec<Summary, 4> = history.hours().collect();
+        assert_eq!(hours.len(), 1);
+        assert_eq!(
+            (hours[0].min, hours[0].max, hours[0].count),
+            (0.0, 59.0, 60)
+        );
+        assert_eq!(history.last_day().unwrap().count, 61);
+    }
+
+    #[test]
+    fn gap_closes_empty_minutes() {
+        let mut history = History::new();
+        history.push(at(0, 10), 20.0);
+        history.push(at(5, 20), 25.0);
+        // The empty minutes are closed but have no summary
+        assert_eq!(history.minutes.len(), 5);
+        assert_eq!(history.minutes().count(), 1);
+        // The minute keeps its start, 10 seconds after the minute
+        history.push(at(6, 5), 21.0);
+        assert_eq!(history.minutes.len(), 5);
+        history.push(at(6, 10), 21.0);
+        assert_eq!(history.minutes.len(), 6);
+    }
+
+    #[test]
+    fn day_is_capped() {
+        let mut history = History::new();
+        for hour in 0..30 {
+            history.push(at(hour * 60, 0), hour as f64);
+        }
+        history.push(at(30 * 60, 0), 30.0);
+        assert_eq!(history.hours.len(), HOURS);
+        assert_eq!(history.minutes.len(), MINUTES);
+        // Only hours 6 to 29 and the minute in progress are left
+        let day = history.last_day().unwrap();
+        assert_eq!((day.min, day.max, day.count), (6.0, 30.0, 25));
+    }
+
+    #[test]
+    fn long_gap_is_capped() {
+        let mut history = History::new();
+        history.push(at(0, 0), 20.0);
+        history.push(at(100_000, 0), 25.0);
+        assert_eq!(history.hours.len(), HOURS);
+        assert_eq!(history.hours().count(), 0);
+        assert_eq!(history.last_day().unwrap().count, 1);
+    }
+
+    #[test]
+    fn reset_drops_readings() {
+        let mut history = History::new();
+        history.push(at(0, 0), 20.0);
+        history.push(at(90, 0), 21.0);
+        history.reset();
+        assert_eq!(history.last_hour(), None);
+        assert_eq!(history.last_day(), None);
+        assert_eq!(history.minutes().count(), 0);
+        assert_eq!(history.hours().count(), 0);
+    }
+
+    #[test]
+    fn stddev() {
+        let mut stats = Stats::new();
+        for _ in 0..1000 {
+            stats.push(21.3);
+        }
+        let summary = stats.summary().unwrap();
+        assert_eq!(summary.stddev, 0.0);
+        assert!((summary.mean - 21.3).abs() < 1e-9);
+
+        let mut stats = Stats::new();
+        for value in [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+            stats.push(value);
+        }
+        assert_eq!(stats.summary().unwrap().stddev, 2.0);
+
+        // Merging halves gives the same result as pushing everything
+        let (mut low, mut high) = (Stats::new(), Stats::new());
+        [2.0, 4.0, 4.0, 4.0]
+            .iter()
+            .for_each(|&value| low.push(value));
+        [5.0, 5.0, 7.0, 9.0]
+            .iter()
+            .for_each(|&value| high.push(value));
+        low.merge(&high);
+        low.merge(&Stats::new());
+        assert_eq!(low.summary(), stats.summary());
+        assert_eq!(Stats::new().summary(), None);
+    }
+
+    #[test]
+    fn square_root() {
+        assert_eq!(sqrt(0.0), 0.0);
+        assert_eq!(sqrt(-1.0), 0.0);
+        assert_eq!(sqrt(4.0), 2.0);
+        assert!((sqrt(0.25) - 0.5).abs() < 1e-12);
+        assert!((sqrt(2.0) - core::f64::consts::SQRT_2).abs() < 1e-12);
+    }
+}